@@ -33,6 +33,19 @@ fn main() -> Result<()> {
         "exit"          => Command::Exit,
         "reload"        => Command::Reload,
         "set"           => Command::Set,
+        "layout"        => Command::Layout,
+        "query"         => Command::Query,
+        "presel"        => Command::Presel,
+        "node"          => Command::Node,
+        "split"         => Command::Split,
+        "remove"        => Command::Remove,
+        "focus_frame"   => Command::FocusFrame,
+        "tag"           => Command::Tag,
+        "window"        => Command::Window,
+        "focus"         => Command::Focus,
+        "swap"          => Command::Swap,
+        "scratchpad"    => Command::Scratchpad,
+        "rule"          => Command::Rule,
         _               => return Err(anyhow!("Invalid command {}", cmd)),
     };
     // let args: Vec<String> = env::args().skip(2).collect();
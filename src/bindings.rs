@@ -172,13 +172,7 @@ fn parse_key_binding(pattern: impl Into<String>, known_codes: &KeymapTable) -> O
         Some(code) => {
             let mask = parts
                 .iter()
-                .map(|s| match s {
-                    &"A" => xcb::MOD_MASK_1,
-                    &"M" => xcb::MOD_MASK_4,
-                    &"S" => xcb::MOD_MASK_SHIFT,
-                    &"C" => xcb::MOD_MASK_CONTROL,
-                    &_ => panic!("invalid key binding prefix: {}", s),
-                })
+                .map(|s| modifier_mask(s).unwrap_or_else(|| panic!("invalid key binding prefix: {}", s)))
                 .fold(0, |acc, v| acc | v);
 
             // debug!("binding '{}' as [{}, {}]", s, mask, code);
@@ -191,6 +185,25 @@ fn parse_key_binding(pattern: impl Into<String>, known_codes: &KeymapTable) -> O
     }
 }
 
+/// The X modifier mask of a modifier name used in bindings (M, A, C or S)
+fn modifier_mask(name: &str) -> Option<u32> {
+    match name {
+        "A" => Some(xcb::MOD_MASK_1),
+        "M" => Some(xcb::MOD_MASK_4),
+        "S" => Some(xcb::MOD_MASK_SHIFT),
+        "C" => Some(xcb::MOD_MASK_CONTROL),
+        _ => None,
+    }
+}
+
+/// Convert modifiers written as in key bindings (e.g. "M" or "M-S") into an
+/// X modifier mask.
+pub fn parse_modifiers(pattern: &str) -> Option<u16> {
+    pattern
+        .split('-')
+        .map(modifier_mask)
+        .try_fold(0, |acc, v| v.map(|v| acc | v as u16))
+}
 
 /**
  * Run an external command
@@ -198,7 +211,7 @@ fn parse_key_binding(pattern: impl Into<String>, known_codes: &KeymapTable) -> O
  * This redirects the process stdout and stderr to /dev/null.
  * Logs a warning if there were any errors in kicking off the process.
  */
-fn spawn(cmd: impl Into<String>) {
+pub(crate) fn spawn(cmd: impl Into<String>) {
     let s = cmd.into();
     let parts: Vec<&str> = s.split_whitespace().collect();
     let result = if parts.len() > 1 {
@@ -19,6 +19,7 @@ pub const IPC_WINDOW_NAME: &str = "WONTWM_IPC";
 pub const IPC_WINDOW_CLASS: &str = "WONTWM_IPC";
 pub const IPC_COMMAND_ATOM: &str = "_WONTWM_IPC_COMMAND";
 pub const IPC_STATE_ATOM: &str = "_WONTWM_IPC_STATE";
+pub const IPC_REPLY_ATOM: &str = "_WONTWM_IPC_REPLY";
 pub const IPC_STATE_SERVER_READY: &str = "server_ready";
 pub const IPC_STATE_REPLY_READY: &str = "reply_ready";
 pub const IPC_STATE_SUCCESS: &str = "success";
@@ -32,6 +33,19 @@ pub enum Command {
     Exit,
     Reload,
     Set,
+    Layout,
+    Query,
+    Presel,
+    Node,
+    Split,
+    Remove,
+    FocusFrame,
+    Tag,
+    Window,
+    Focus,
+    Swap,
+    Scratchpad,
+    Rule,
     // Invalid,
 }
 
@@ -41,6 +55,7 @@ pub struct IpcClient {
     ipc_win: Window, 
     atom_command: Atom,
     atom_state: Atom,
+    atom_reply: Atom,
 }
 
 impl IpcClient {
@@ -82,6 +97,7 @@ impl IpcClient {
 
         let atom_command = conn.intern_atom(IPC_COMMAND_ATOM)?;
         let atom_state = conn.intern_atom(IPC_STATE_ATOM)?;
+        let atom_reply = conn.intern_atom(IPC_REPLY_ATOM)?;
 
         Ok(IpcClient {
             conn,
@@ -89,6 +105,7 @@ impl IpcClient {
             ipc_win,
             atom_command,
             atom_state,
+            atom_reply,
         })
     }
 
@@ -155,7 +172,7 @@ impl IpcClient {
                                 // No reply required
                                 IPC_STATE_SUCCESS => return Ok(IPC_STATE_SUCCESS.to_string()),
                                 IPC_STATE_REPLY_READY => {
-                                    return self.conn.get_text_property(id, self.atom_command);
+                                    return self.conn.get_text_property(id, self.atom_reply);
                                 }
                                 IPC_STATE_ERROR => {
                                     return Ok(IPC_STATE_ERROR.to_string())
@@ -176,6 +193,7 @@ pub struct IpcServer<'a> {
     conn: &'a XcbConnection,
     atom_command: Atom,
     atom_state: Atom,
+    atom_reply: Atom,
 }
 
 impl<'a> IpcServer<'a> {
@@ -183,10 +201,12 @@ impl<'a> IpcServer<'a> {
 
         let atom_command = conn.intern_atom(IPC_COMMAND_ATOM)?;
         let atom_state = conn.intern_atom(IPC_STATE_ATOM)?;
+        let atom_reply = conn.intern_atom(IPC_REPLY_ATOM)?;
         Ok(IpcServer {
             conn,
             atom_command,
             atom_state,
+            atom_reply,
         })
     }
 
@@ -226,4 +246,11 @@ impl<'a> IpcServer<'a> {
     pub fn send_reply(&self, win: Window, data: &str) {
         self.conn.set_text_property(win, self.atom_state, data);
     }
+
+    /// Reply with some data, which is written to a separate property so that
+    /// it isn't picked up as a new command.
+    pub fn send_data(&self, win: Window, data: &str) {
+        self.conn.set_text_property(win, self.atom_reply, data);
+        self.conn.set_text_property(win, self.atom_state, IPC_STATE_REPLY_READY);
+    }
 }
@@ -0,0 +1,902 @@
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use xcb::Window;
+
+use crate::xconnection::Rectangle;
+
+mod bsp;
+mod frames;
+
+pub use bsp::Bsp;
+pub use frames::Frames;
+
+/// The smallest and largest main_ratio a layout will accept
+pub const MIN_MAIN_RATIO: f32 = 0.05;
+pub const MAX_MAIN_RATIO: f32 = 0.95;
+
+/// Per-tag parameters passed to a layout
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LayoutParams {
+    /// Number of windows in the main (master) area
+    pub nmaster: u32,
+    /// Fraction of the frame given to the main area
+    pub main_ratio: f32,
+}
+
+/// A direction on screen, used by directional commands
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Direction {
+    West,
+    East,
+    North,
+    South,
+}
+
+impl Direction {
+    /// The axis a move in this direction happens on
+    fn axis(self) -> Axis {
+        match self {
+            Direction::West | Direction::East => Axis::X,
+            Direction::North | Direction::South => Axis::Y,
+        }
+    }
+
+    /// True for directions going towards the top left corner
+    fn is_backward(self) -> bool {
+        self == Direction::West || self == Direction::North
+    }
+
+    fn opposite(self) -> Direction {
+        match self {
+            Direction::West => Direction::East,
+            Direction::East => Direction::West,
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+        }
+    }
+
+    /// The name of the direction, as accepted by from_str
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::West => "west",
+            Direction::East => "east",
+            Direction::North => "north",
+            Direction::South => "south",
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Direction> {
+        match s {
+            "west" | "left" => Ok(Direction::West),
+            "east" | "right" => Ok(Direction::East),
+            "north" | "up" | "top" => Ok(Direction::North),
+            "south" | "down" | "bottom" => Ok(Direction::South),
+            _ => Err(anyhow!("invalid direction {}", s)),
+        }
+    }
+}
+
+/// The axis along which a region is split in two, used by tree based layouts
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Axis {
+    /// Children side by side, first on the left
+    X,
+    /// Children on top of each other, first on the top
+    Y,
+}
+
+impl Axis {
+    fn other(self) -> Axis {
+        match self {
+            Axis::X => Axis::Y,
+            Axis::Y => Axis::X,
+        }
+    }
+}
+
+/// Split a region in two along `axis`, giving `ratio` of it to the first part
+fn split_region(region: &Rectangle, axis: Axis, ratio: f32) -> (Rectangle, Rectangle) {
+    let (x, y, w, h) = region.values();
+    match axis {
+        Axis::X => {
+            let first_w = (w as f32 * ratio) as u32;
+            (
+                Rectangle::new(x, y, first_w, h),
+                Rectangle::new(x + first_w as i32, y, w - first_w, h),
+            )
+        }
+        Axis::Y => {
+            let first_h = (h as f32 * ratio) as u32;
+            (
+                Rectangle::new(x, y, w, first_h),
+                Rectangle::new(x, y + first_h as i32, w, h - first_h),
+            )
+        }
+    }
+}
+
+/**
+ * Find the rectangle closest to `from` in the given direction.
+ *
+ * Candidates must lie entirely beyond the matching edge of `from`. Those that
+ * overlap `from` on the other axis are preferred, then the nearest one wins.
+ * Returns the index of the chosen candidate.
+ */
+pub fn find_in_direction(from: &Rectangle, candidates: &[Rectangle], dir: Direction) -> Option<usize> {
+    let (fx, fy, fw, fh) = from.values();
+    let (fx2, fy2) = (fx + fw as i32, fy + fh as i32);
+    candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| {
+            let (cx, cy, cw, ch) = c.values();
+            let (cx2, cy2) = (cx + cw as i32, cy + ch as i32);
+            let distance = match dir {
+                Direction::West => fx - cx2,
+                Direction::East => cx - fx2,
+                Direction::North => fy - cy2,
+                Direction::South => cy - fy2,
+            };
+            if distance < 0 {
+                return None;
+            }
+            // How far apart the two rectangles are on the other axis, 0 if they overlap
+            let offset = match dir.axis() {
+                Axis::X => (cy - fy2).max(fy - cy2).max(0),
+                Axis::Y => (cx - fx2).max(fx - cx2).max(0),
+            };
+            Some(((offset, distance), i))
+        })
+        .min()
+        .map(|(_, i)| i)
+}
+
+/**
+ * Mirror and rotate transformations applied on top of any layout.
+ *
+ * The layout runs as usual (in a frame with swapped sides when rotating) and
+ * its result is then mapped back onto the real frame. Rotation is clockwise
+ * and happens before mirroring, so e.g. rotating tall puts the main area on top.
+ */
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Transform {
+    /// Mirror horizontally: left becomes right
+    pub mirror_x: bool,
+    /// Mirror vertically: top becomes bottom
+    pub mirror_y: bool,
+    /// Rotate by 90 degrees
+    pub rotate: bool,
+}
+
+impl Transform {
+    /// Run a layout and transform its result to fit `frame`
+    pub fn arrange(
+        &self,
+        layout: &mut dyn Layout,
+        frame: &Rectangle,
+        windows: &[Window],
+        params: &LayoutParams,
+        focused: Option<Window>,
+    ) -> Vec<Rectangle> {
+        let (x, y, w, h) = frame.values();
+        let layout_frame = if self.rotate { Rectangle::new(x, y, h, w) } else { *frame };
+        layout
+            .arrange(&layout_frame, windows, params, focused)
+            .iter()
+            .map(|r| self.apply(frame, r))
+            .collect()
+    }
+
+    /// Map a region computed by a layout back onto the real frame
+    fn apply(&self, frame: &Rectangle, region: &Rectangle) -> Rectangle {
+        let (fx, fy, fw, fh) = frame.values();
+        let (x, y, mut w, mut h) = region.values();
+        // Work relative to the top left corner of the frame
+        let (mut dx, mut dy) = (x - fx, y - fy);
+        if self.rotate {
+            // The layout frame is fh wide and fw high
+            let rotated = (fw as i32 - dy - h as i32, dx);
+            dx = rotated.0;
+            dy = rotated.1;
+            std::mem::swap(&mut w, &mut h);
+        }
+        if self.mirror_x {
+            dx = fw as i32 - dx - w as i32;
+        }
+        if self.mirror_y {
+            dy = fh as i32 - dy - h as i32;
+        }
+        Rectangle::new(fx + dx, fy + dy, w, h)
+    }
+
+    /// Map a direction on screen to the same direction in the frame the
+    /// layout works in, undoing the mirroring and then the rotation
+    pub fn layout_direction(&self, dir: Direction) -> Direction {
+        let mut dir = dir;
+        if (self.mirror_x && dir.axis() == Axis::X) || (self.mirror_y && dir.axis() == Axis::Y) {
+            dir = dir.opposite();
+        }
+        if self.rotate {
+            // Clockwise rotation turns the layout's east into south, and so on
+            dir = match dir {
+                Direction::South => Direction::East,
+                Direction::West => Direction::South,
+                Direction::North => Direction::West,
+                Direction::East => Direction::North,
+            };
+        }
+        dir
+    }
+
+    /**
+     * Rewrite the arguments of a layout specific command, which are given as
+     * seen on screen, for the frame the layout works in.
+     *
+     * Directions go through layout_direction, horizontal and vertical trade
+     * places when rotating, and a single mirror reverses `node rotate`.
+     */
+    pub fn layout_args(&self, args: &[&str]) -> Vec<String> {
+        let mirrored = self.mirror_x != self.mirror_y;
+        let node_rotate = args.starts_with(&["node", "rotate"]);
+        args.iter()
+            .enumerate()
+            .map(|(i, &arg)| {
+                if let Ok(dir) = arg.parse::<Direction>() {
+                    return self.layout_direction(dir).name().to_string();
+                }
+                let arg = match arg {
+                    "horizontal" if self.rotate => "vertical",
+                    "vertical" if self.rotate => "horizontal",
+                    "90" if mirrored && node_rotate && i == 2 => "270",
+                    "270" if mirrored && node_rotate && i == 2 => "90",
+                    arg => arg,
+                };
+                arg.to_string()
+            })
+            .collect()
+    }
+
+    /// Short description for status queries, empty when nothing is applied
+    pub fn status(&self) -> String {
+        let mut flags = vec![];
+        if self.rotate {
+            flags.push("rotate");
+        }
+        if self.mirror_x {
+            flags.push("mirror_x");
+        }
+        if self.mirror_y {
+            flags.push("mirror_y");
+        }
+        flags.join(" ")
+    }
+}
+
+/**
+ * A tiling algorithm.
+ *
+ * A layout receives the frame it is allowed to use and the ordered windows of a
+ * tag, and returns one geometry per window, in the same order as the windows.
+ * It never talks to the X server itself: the WindowManager applies the result.
+ *
+ * Every tag owns its own instance, so a layout may keep state between calls
+ * (e.g. a tree of splits). The windows of the tag can change between two calls
+ * to arrange and stateful layouts are expected to catch up with them.
+ */
+pub trait Layout {
+    /// The name used to refer to this layout from wontdo
+    fn name(&self) -> &'static str;
+
+    /// Compute the geometry of every window within the given frame
+    fn arrange(
+        &mut self,
+        frame: &Rectangle,
+        windows: &[Window],
+        params: &LayoutParams,
+        focused: Option<Window>,
+    ) -> Vec<Rectangle>;
+
+    /// A short description of the layout state, for status bars
+    fn status(&self, _windows: &[Window], _focused: Option<Window>) -> String {
+        self.name().to_string()
+    }
+
+    /// Handle a layout specific ipc command. `args` starts with the command name.
+    /// Returns a window that should be given the focus, if any.
+    fn command(&mut self, args: &[&str], _focused: Option<Window>) -> Result<Option<Window>> {
+        Err(anyhow!("layout {} does not support {}", self.name(), args.join(" ")))
+    }
+
+    /// Two windows swapped places: each one takes the place of the other.
+    /// If only one of them is known, the other one replaces it. Layouts that
+    /// place windows by their order in the tag have nothing to do.
+    fn swap_windows(&mut self, _a: Window, _b: Window) {}
+}
+
+/// Exchange a and b in a window slot, leaving any other window alone
+pub(crate) fn swap_id(win: &mut Window, a: Window, b: Window) {
+    if *win == a {
+        *win = b;
+    } else if *win == b {
+        *win = a;
+    }
+}
+
+/// Creates a fresh instance of a layout
+pub type LayoutBuilder = fn() -> Box<dyn Layout>;
+
+/// The set of named layouts known to the WindowManager
+pub struct LayoutRegistry {
+    layouts: Vec<(&'static str, LayoutBuilder)>,
+}
+
+impl LayoutRegistry {
+    /// Create an empty registry
+    pub fn new() -> LayoutRegistry {
+        LayoutRegistry {
+            layouts: Vec::new(),
+        }
+    }
+
+    /// Create a registry containing all built-in layouts.
+    /// The first one is used by default.
+    pub fn default() -> LayoutRegistry {
+        let mut registry = LayoutRegistry::new();
+        registry.register(|| Box::new(Columns));
+        registry.register(|| Box::new(Tall));
+        registry.register(|| Box::new(CenteredMaster));
+        registry.register(|| Box::new(ThreeColumn));
+        registry.register(|| Box::new(Monocle));
+        registry.register(|| Box::new(Rows));
+        registry.register(|| Box::new(Grid));
+        registry.register(|| Box::new(Spiral));
+        registry.register(|| Box::new(Dwindle));
+        registry.register(|| Box::new(Bsp::new()));
+        registry.register(|| Box::new(Frames::new()));
+        registry
+    }
+
+    /// Add a layout, replacing any existing layout with the same name
+    pub fn register(&mut self, builder: LayoutBuilder) {
+        let name = builder().name();
+        match self.index_of(name) {
+            Some(i) => self.layouts[i] = (name, builder),
+            None => self.layouts.push((name, builder)),
+        }
+    }
+
+    /// Create a new instance of the layout at index
+    pub fn build(&self, index: usize) -> Option<Box<dyn Layout>> {
+        self.layouts.get(index).map(|(_, builder)| builder())
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.layouts.iter().position(|(n, _)| *n == name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.layouts.len()
+    }
+}
+
+/// Split `total` pixels into `n` parts and return the (offset, size) of part `i`.
+/// The remainder of the division is spread over the first parts.
+pub fn split_even(total: u32, n: usize, i: usize) -> (u32, u32) {
+    let n = n as u32;
+    let i = i as u32;
+    let base = total / n;
+    let extra = total % n;
+    let offset = base * i + i.min(extra);
+    let size = base + if i < extra { 1 } else { 0 };
+    (offset, size)
+}
+
+/// Split a frame into a grid of cells that is as close to square as possible.
+/// The last row is stretched if the windows don't fill it.
+fn grid(frame: &Rectangle, n: usize) -> Vec<Rectangle> {
+    if n == 0 {
+        return Vec::new();
+    }
+    let (x, y, w, h) = frame.values();
+    let cols = (n as f32).sqrt().ceil() as usize;
+    let rows = n.div_ceil(cols);
+    (0..n)
+        .map(|i| {
+            let row = i / cols;
+            // The last row may have fewer windows, which then share its width
+            let cols_in_row = if row == rows - 1 { n - row * cols } else { cols };
+            let (x_off, cell_w) = split_even(w, cols_in_row, i % cols);
+            let (y_off, cell_h) = split_even(h, rows, row);
+            Rectangle::new(x + x_off as i32, y + y_off as i32, cell_w, cell_h)
+        })
+        .collect()
+}
+
+/// Stack `n` windows on top of each other in a column of the frame
+fn column(frame: &Rectangle, x: i32, w: u32, n: usize) -> Vec<Rectangle> {
+    let (_, y, _, h) = frame.values();
+    (0..n)
+        .map(|i| {
+            let (offset, height) = split_even(h, n, i);
+            Rectangle::new(x, y + offset as i32, w, height)
+        })
+        .collect()
+}
+
+/**
+ * Fibonacci tiling: every window takes a part of the remaining space, which is
+ * split in two alternately along the x and y axis. The first split uses
+ * main_ratio, the next ones halve the remaining space.
+ *
+ * With `spiral` the windows wind clockwise towards the center, otherwise they
+ * dwindle towards the bottom right corner.
+ */
+fn fibonacci(frame: &Rectangle, n: usize, main_ratio: f32, spiral: bool) -> Vec<Rectangle> {
+    let mut regions = Vec::with_capacity(n);
+    let mut remaining = *frame;
+    for i in 0..n {
+        if i == n - 1 {
+            regions.push(remaining);
+            break;
+        }
+        let axis = if i % 2 == 0 { Axis::X } else { Axis::Y };
+        let ratio = if i == 0 { main_ratio } else { 0.5 };
+        let (first, second) = split_region(&remaining, axis, ratio);
+        // Going around clockwise, every other pair of windows takes the far side
+        if spiral && i % 4 >= 2 {
+            regions.push(second);
+            remaining = first;
+        } else {
+            regions.push(first);
+            remaining = second;
+        }
+    }
+    regions
+}
+
+/// Equal width columns, side by side
+pub struct Columns;
+
+impl Layout for Columns {
+    fn name(&self) -> &'static str {
+        "columns"
+    }
+
+    fn arrange(
+        &mut self,
+        frame: &Rectangle,
+        windows: &[Window],
+        _params: &LayoutParams,
+        _focused: Option<Window>,
+    ) -> Vec<Rectangle> {
+        let (x, y, w, h) = frame.values();
+        (0..windows.len())
+            .map(|i| {
+                let (offset, width) = split_even(w, windows.len(), i);
+                Rectangle::new(x + offset as i32, y, width, h)
+            })
+            .collect()
+    }
+}
+
+/// Equal height rows, stacked from top to bottom
+pub struct Rows;
+
+impl Layout for Rows {
+    fn name(&self) -> &'static str {
+        "rows"
+    }
+
+    fn arrange(
+        &mut self,
+        frame: &Rectangle,
+        windows: &[Window],
+        _params: &LayoutParams,
+        _focused: Option<Window>,
+    ) -> Vec<Rectangle> {
+        let (x, y, w, h) = frame.values();
+        (0..windows.len())
+            .map(|i| {
+                let (offset, height) = split_even(h, windows.len(), i);
+                Rectangle::new(x, y + offset as i32, w, height)
+            })
+            .collect()
+    }
+}
+
+/**
+ * dwm / xmonad style master and stack.
+ *
+ * The first `nmaster` windows share a column on the left taking `main_ratio`
+ * of the frame width, the rest are stacked in a column on the right. If either
+ * area is empty the other one takes the whole frame.
+ */
+pub struct Tall;
+
+impl Layout for Tall {
+    fn name(&self) -> &'static str {
+        "tall"
+    }
+
+    fn arrange(
+        &mut self,
+        frame: &Rectangle,
+        windows: &[Window],
+        params: &LayoutParams,
+        _focused: Option<Window>,
+    ) -> Vec<Rectangle> {
+        let n = windows.len();
+        let nmaster = (params.nmaster as usize).min(n);
+        let (x, y, w, h) = frame.values();
+        let main_w = if nmaster == 0 {
+            0
+        } else if nmaster == n {
+            w
+        } else {
+            (w as f32 * params.main_ratio) as u32
+        };
+
+        (0..n)
+            .map(|i| {
+                if i < nmaster {
+                    let (offset, height) = split_even(h, nmaster, i);
+                    Rectangle::new(x, y + offset as i32, main_w, height)
+                } else {
+                    let (offset, height) = split_even(h, n - nmaster, i - nmaster);
+                    Rectangle::new(x + main_w as i32, y + offset as i32, w - main_w, height)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Every window takes the whole frame, only the focused one is visible
+pub struct Monocle;
+
+impl Layout for Monocle {
+    fn name(&self) -> &'static str {
+        "monocle"
+    }
+
+    fn arrange(
+        &mut self,
+        frame: &Rectangle,
+        windows: &[Window],
+        _params: &LayoutParams,
+        _focused: Option<Window>,
+    ) -> Vec<Rectangle> {
+        windows.iter().map(|_| *frame).collect()
+    }
+
+    /// "monocle <focused index>/<count>", with a 1 based index (0 if nothing is focused)
+    fn status(&self, windows: &[Window], focused: Option<Window>) -> String {
+        let index = focused
+            .and_then(|f| windows.iter().position(|&w| w == f))
+            .map_or(0, |i| i + 1);
+        format!("{} {}/{}", self.name(), index, windows.len())
+    }
+}
+
+/// Windows in rows and columns, as close to a square grid as possible
+pub struct Grid;
+
+impl Layout for Grid {
+    fn name(&self) -> &'static str {
+        "grid"
+    }
+
+    fn arrange(
+        &mut self,
+        frame: &Rectangle,
+        windows: &[Window],
+        _params: &LayoutParams,
+        _focused: Option<Window>,
+    ) -> Vec<Rectangle> {
+        grid(frame, windows.len())
+    }
+}
+
+/// Fibonacci spiral, windows get smaller going clockwise towards the center
+pub struct Spiral;
+
+impl Layout for Spiral {
+    fn name(&self) -> &'static str {
+        "spiral"
+    }
+
+    fn arrange(
+        &mut self,
+        frame: &Rectangle,
+        windows: &[Window],
+        params: &LayoutParams,
+        _focused: Option<Window>,
+    ) -> Vec<Rectangle> {
+        fibonacci(frame, windows.len(), params.main_ratio, true)
+    }
+}
+
+/// Fibonacci dwindle, windows get smaller towards the bottom right corner
+pub struct Dwindle;
+
+impl Layout for Dwindle {
+    fn name(&self) -> &'static str {
+        "dwindle"
+    }
+
+    fn arrange(
+        &mut self,
+        frame: &Rectangle,
+        windows: &[Window],
+        params: &LayoutParams,
+        _focused: Option<Window>,
+    ) -> Vec<Rectangle> {
+        fibonacci(frame, windows.len(), params.main_ratio, false)
+    }
+}
+
+/**
+ * dwm style centered master, for wide monitors.
+ *
+ * The main area takes `main_ratio` of the width in the middle of the frame and
+ * the other windows alternate between a column on its right and on its left.
+ * With a single window in the stack the main area moves to the left, as in tall.
+ */
+pub struct CenteredMaster;
+
+impl Layout for CenteredMaster {
+    fn name(&self) -> &'static str {
+        "centered_master"
+    }
+
+    fn arrange(
+        &mut self,
+        frame: &Rectangle,
+        windows: &[Window],
+        params: &LayoutParams,
+        _focused: Option<Window>,
+    ) -> Vec<Rectangle> {
+        let n = windows.len();
+        let nmaster = (params.nmaster as usize).min(n);
+        let nstack = n - nmaster;
+        if nstack <= 1 {
+            return Tall.arrange(frame, windows, params, None);
+        }
+
+        let (x, _, w, _) = frame.values();
+        let main_w = if nmaster == 0 { 0 } else { (w as f32 * params.main_ratio) as u32 };
+        let left_w = (w - main_w) / 2;
+        let right_w = w - main_w - left_w;
+        let main = column(frame, x + left_w as i32, main_w, nmaster);
+        // Stack windows go right, left, right, left...
+        let nright = nstack.div_ceil(2);
+        let right = column(frame, x + (left_w + main_w) as i32, right_w, nright);
+        let left = column(frame, x, left_w, nstack - nright);
+
+        main.into_iter()
+            .chain((0..nstack).map(|i| if i % 2 == 0 { right[i / 2] } else { left[i / 2] }))
+            .collect()
+    }
+}
+
+/**
+ * xmonad style three columns: the main area on the left takes `main_ratio` of
+ * the width and the other windows are split between two columns on its right.
+ * With a single window in the stack this is the same as tall.
+ */
+pub struct ThreeColumn;
+
+impl Layout for ThreeColumn {
+    fn name(&self) -> &'static str {
+        "three_column"
+    }
+
+    fn arrange(
+        &mut self,
+        frame: &Rectangle,
+        windows: &[Window],
+        params: &LayoutParams,
+        _focused: Option<Window>,
+    ) -> Vec<Rectangle> {
+        let n = windows.len();
+        let nmaster = (params.nmaster as usize).min(n);
+        let nstack = n - nmaster;
+        if nstack <= 1 {
+            return Tall.arrange(frame, windows, params, None);
+        }
+
+        let (x, _, w, _) = frame.values();
+        let main_w = if nmaster == 0 { 0 } else { (w as f32 * params.main_ratio) as u32 };
+        let middle_w = (w - main_w) / 2;
+        let right_w = w - main_w - middle_w;
+        // The middle column gets the extra window when the count is odd
+        let nmiddle = nstack.div_ceil(2);
+        let mut regions = column(frame, x, main_w, nmaster);
+        regions.extend(column(frame, x + main_w as i32, middle_w, nmiddle));
+        regions.extend(column(frame, x + (main_w + middle_w) as i32, right_w, nstack - nmiddle));
+        regions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: LayoutParams = LayoutParams { nmaster: 1, main_ratio: 0.5 };
+    const DIRECTIONS: [Direction; 4] = [Direction::West, Direction::East, Direction::North, Direction::South];
+
+    fn transform(mirror_x: bool, mirror_y: bool, rotate: bool) -> Transform {
+        Transform { mirror_x, mirror_y, rotate }
+    }
+
+    fn all_transforms() -> Vec<Transform> {
+        (0..8).map(|i| transform(i & 1 != 0, i & 2 != 0, i & 4 != 0)).collect()
+    }
+
+    /// Check that a layout covers an odd sized frame exactly, without
+    /// overlaps, for 1, 2, 3, 5 and 8 windows
+    fn assert_tiles(layout: &mut dyn Layout, params: &LayoutParams) {
+        let frame = Rectangle::new(10, 20, 301, 157);
+        for n in [1, 2, 3, 5, 8] {
+            let windows: Vec<Window> = (1..=n).collect();
+            let regions: Vec<_> = layout
+                .arrange(&frame, &windows, params, None)
+                .iter()
+                .map(Rectangle::values)
+                .collect();
+            assert_eq!(regions.len(), windows.len(), "{} with {} windows", layout.name(), n);
+            let area: u32 = regions.iter().map(|&(_, _, w, h)| w * h).sum();
+            assert_eq!(area, 301 * 157, "{} with {} windows: {:?}", layout.name(), n, regions);
+            for (i, &(x, y, w, h)) in regions.iter().enumerate() {
+                assert!(w > 0 && h > 0, "{} with {} windows: {:?}", layout.name(), n, regions);
+                assert!(x >= 10 && y >= 20 && x + w as i32 <= 311 && y + h as i32 <= 177);
+                for &(x2, y2, w2, h2) in &regions[i + 1..] {
+                    let overlap_x = x < x2 + w2 as i32 && x2 < x + w as i32;
+                    let overlap_y = y < y2 + h2 as i32 && y2 < y + h as i32;
+                    assert!(!(overlap_x && overlap_y), "{} with {} windows: {:?}", layout.name(), n, regions);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tall_tiles_the_frame() {
+        assert_tiles(&mut Tall, &PARAMS);
+        assert_tiles(&mut Tall, &LayoutParams { nmaster: 2, main_ratio: 0.6 });
+        assert_tiles(&mut Tall, &LayoutParams { nmaster: 0, main_ratio: 0.5 });
+        let frame = Rectangle::new(0, 0, 100, 60);
+        let regions = Tall.arrange(&frame, &[1, 2, 3], &PARAMS, None);
+        let regions: Vec<_> = regions.iter().map(Rectangle::values).collect();
+        assert_eq!(regions, vec![(0, 0, 50, 60), (50, 0, 50, 30), (50, 30, 50, 30)]);
+    }
+
+    #[test]
+    fn grid_tiles_the_frame() {
+        assert_tiles(&mut Grid, &PARAMS);
+        let frame = Rectangle::new(0, 0, 100, 60);
+        let regions = Grid.arrange(&frame, &[1, 2, 3], &PARAMS, None);
+        let regions: Vec<_> = regions.iter().map(Rectangle::values).collect();
+        assert_eq!(regions, vec![(0, 0, 50, 30), (50, 0, 50, 30), (0, 30, 100, 30)]);
+    }
+
+    #[test]
+    fn spiral_and_dwindle_tile_the_frame() {
+        for ratio in [0.5, 0.3, 0.7] {
+            let params = LayoutParams { nmaster: 1, main_ratio: ratio };
+            assert_tiles(&mut Spiral, &params);
+            assert_tiles(&mut Dwindle, &params);
+        }
+        // The fourth window goes clockwise in a spiral, right in a dwindle
+        let frame = Rectangle::new(0, 0, 120, 80);
+        let spiral = Spiral.arrange(&frame, &[1, 2, 3, 4], &PARAMS, None);
+        let spiral: Vec<_> = spiral.iter().map(Rectangle::values).collect();
+        assert_eq!(spiral, vec![(0, 0, 60, 80), (60, 0, 60, 40), (90, 40, 30, 40), (60, 40, 30, 40)]);
+        let dwindle = Dwindle.arrange(&frame, &[1, 2, 3, 4], &PARAMS, None);
+        let dwindle: Vec<_> = dwindle.iter().map(Rectangle::values).collect();
+        assert_eq!(dwindle, vec![(0, 0, 60, 80), (60, 0, 60, 40), (60, 40, 30, 40), (90, 40, 30, 40)]);
+    }
+
+    #[test]
+    fn centered_master_and_three_column_tile_the_frame() {
+        for nmaster in [0, 1, 2] {
+            let params = LayoutParams { nmaster, main_ratio: 0.5 };
+            assert_tiles(&mut CenteredMaster, &params);
+            assert_tiles(&mut ThreeColumn, &params);
+        }
+        let frame = Rectangle::new(0, 0, 120, 60);
+        let centered = CenteredMaster.arrange(&frame, &[1, 2, 3], &PARAMS, None);
+        let centered: Vec<_> = centered.iter().map(Rectangle::values).collect();
+        assert_eq!(centered, vec![(30, 0, 60, 60), (90, 0, 30, 60), (0, 0, 30, 60)]);
+        let three = ThreeColumn.arrange(&frame, &[1, 2, 3], &PARAMS, None);
+        let three: Vec<_> = three.iter().map(Rectangle::values).collect();
+        assert_eq!(three, vec![(0, 0, 60, 60), (60, 0, 30, 60), (90, 0, 30, 60)]);
+        // A single stack window falls back on tall
+        let tall = Tall.arrange(&frame, &[1, 2], &PARAMS, None);
+        assert_eq!(CenteredMaster.arrange(&frame, &[1, 2], &PARAMS, None), tall);
+        assert_eq!(ThreeColumn.arrange(&frame, &[1, 2], &PARAMS, None), tall);
+    }
+
+    #[test]
+    fn apply_mirrors_within_the_frame() {
+        let frame = Rectangle::new(10, 20, 200, 100);
+        let left = Rectangle::new(10, 20, 50, 100);
+        let top = Rectangle::new(10, 20, 200, 30);
+        assert_eq!(Transform::default().apply(&frame, &left), left);
+        assert_eq!(transform(true, false, false).apply(&frame, &left).values(), (160, 20, 50, 100));
+        assert_eq!(transform(false, true, false).apply(&frame, &top).values(), (10, 90, 200, 30));
+        assert_eq!(transform(true, true, false).apply(&frame, &left).values(), (160, 20, 50, 100));
+    }
+
+    #[test]
+    fn apply_rotates_clockwise_before_mirroring() {
+        // The layout works in a 100x200 frame
+        let frame = Rectangle::new(10, 20, 200, 100);
+        let left = Rectangle::new(10, 20, 40, 200);
+        let top = Rectangle::new(10, 20, 100, 50);
+        assert_eq!(transform(false, false, true).apply(&frame, &left).values(), (10, 20, 200, 40));
+        assert_eq!(transform(false, false, true).apply(&frame, &top).values(), (160, 20, 50, 100));
+        assert_eq!(transform(false, true, true).apply(&frame, &left).values(), (10, 80, 200, 40));
+        assert_eq!(transform(true, false, true).apply(&frame, &top).values(), (10, 20, 50, 100));
+    }
+
+    #[test]
+    fn arrange_covers_the_frame_with_every_transform() {
+        let frame = Rectangle::new(10, 20, 200, 100);
+        for t in all_transforms() {
+            let regions = t.arrange(&mut Tall, &frame, &[1, 2, 3], &PARAMS, None);
+            let area: u32 = regions.iter().map(|r| r.values()).map(|(_, _, w, h)| w * h).sum();
+            assert_eq!(area, 200 * 100, "{:?}", t);
+            for (x, y, w, h) in regions.iter().map(Rectangle::values) {
+                assert!(x >= 10 && y >= 20, "{:?} {:?}", t, (x, y));
+                assert!(x + w as i32 <= 210 && y + h as i32 <= 120, "{:?} {:?}", t, (x, y, w, h));
+            }
+        }
+    }
+
+    /// The cell next to the center of a 3x3 grid of 10x10 cells
+    fn neighbour(x: i32, y: i32, dir: Direction) -> (i32, i32) {
+        match dir {
+            Direction::West => (x - 10, y),
+            Direction::East => (x + 10, y),
+            Direction::North => (x, y - 10),
+            Direction::South => (x, y + 10),
+        }
+    }
+
+    #[test]
+    fn layout_direction_points_the_same_way_on_screen() {
+        let frame = Rectangle::new(0, 0, 30, 30);
+        let center = Rectangle::new(10, 10, 10, 10);
+        for t in all_transforms() {
+            for &dir in &DIRECTIONS {
+                let (x, y) = neighbour(10, 10, t.layout_direction(dir));
+                let (x, y, _, _) = t.apply(&frame, &Rectangle::new(x, y, 10, 10)).values();
+                assert_eq!((x, y), neighbour(10, 10, dir), "{:?} {:?}", t, dir);
+            }
+            assert_eq!(t.apply(&frame, &center), center);
+        }
+    }
+
+    #[test]
+    fn layout_args_maps_screen_directions() {
+        let rotate = transform(false, false, true);
+        assert_eq!(rotate.layout_args(&["presel", "west", "0.3"]), vec!["presel", "south", "0.3"]);
+        assert_eq!(rotate.layout_args(&["split", "top"]), vec!["split", "west"]);
+        assert_eq!(rotate.layout_args(&["node", "flip", "horizontal"]), vec!["node", "flip", "vertical"]);
+        assert_eq!(rotate.layout_args(&["node", "rotate", "90"]), vec!["node", "rotate", "90"]);
+
+        let mirror_x = transform(true, false, false);
+        assert_eq!(mirror_x.layout_args(&["focus_frame", "east"]), vec!["focus_frame", "west"]);
+        assert_eq!(mirror_x.layout_args(&["focus_frame", "north"]), vec!["focus_frame", "north"]);
+        assert_eq!(mirror_x.layout_args(&["node", "rotate", "90"]), vec!["node", "rotate", "270"]);
+        assert_eq!(mirror_x.layout_args(&["node", "flip", "horizontal"]), vec!["node", "flip", "horizontal"]);
+
+        let both = transform(true, true, false);
+        assert_eq!(both.layout_args(&["node", "rotate", "270", "parent"]), vec!["node", "rotate", "270", "parent"]);
+        assert_eq!(both.layout_args(&["split", "bottom"]), vec!["split", "north"]);
+    }
+}
@@ -0,0 +1,608 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, Context, anyhow};
+use xcb::Window;
+
+use crate::{
+    layouts::{Layout, LayoutParams, Direction, Axis, split_region, swap_id, MIN_MAIN_RATIO, MAX_MAIN_RATIO},
+    xconnection::Rectangle,
+};
+
+const DEFAULT_SPLIT_RATIO: f32 = 0.5;
+
+/// How the split direction is chosen when a window is inserted without preselection
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Scheme {
+    /// Split along the longest side of the focused node
+    LongestSide,
+    /// Split across the direction of the parent split
+    Alternate,
+    /// Like alternate, but new windows go first every other split so the
+    /// windows wind around the center
+    Spiral,
+}
+
+/// A pending split of a window, set with `presel`
+#[derive(Debug, Clone, Copy)]
+struct Presel {
+    win: Window,
+    dir: Direction,
+    ratio: f32,
+}
+
+#[derive(Debug)]
+enum Node {
+    Leaf(Window),
+    Split {
+        axis: Axis,
+        /// Fraction of the node given to the first child
+        ratio: f32,
+        first: Box<Node>,
+        second: Box<Node>,
+    },
+}
+
+impl Node {
+    fn is_leaf(&self, win: Window) -> bool {
+        matches!(self, Node::Leaf(w) if *w == win)
+    }
+
+    fn leaves(&self, out: &mut Vec<Window>) {
+        match self {
+            Node::Leaf(w) => out.push(*w),
+            Node::Split { first, second, .. } => {
+                first.leaves(out);
+                second.leaves(out);
+            }
+        }
+    }
+
+    fn swap_leaves(&mut self, a: Window, b: Window) {
+        match self {
+            Node::Leaf(w) => swap_id(w, a, b),
+            Node::Split { first, second, .. } => {
+                first.swap_leaves(a, b);
+                second.swap_leaves(a, b);
+            }
+        }
+    }
+
+    fn leaf_count(&self) -> usize {
+        match self {
+            Node::Leaf(_) => 1,
+            Node::Split { first, second, .. } => first.leaf_count() + second.leaf_count(),
+        }
+    }
+
+    fn regions(&self, region: &Rectangle, out: &mut HashMap<Window, Rectangle>) {
+        match self {
+            Node::Leaf(w) => {
+                out.insert(*w, *region);
+            }
+            Node::Split { axis, ratio, first, second } => {
+                let (r1, r2) = split_region(region, *axis, *ratio);
+                first.regions(&r1, out);
+                second.regions(&r2, out);
+            }
+        }
+    }
+
+    /// The axis of the split directly above the leaf `win` and whether the
+    /// leaf is its first child
+    fn parent_split(&self, win: Window) -> Option<(Axis, bool)> {
+        match self {
+            Node::Leaf(_) => None,
+            Node::Split { axis, first, second, .. } => {
+                if first.is_leaf(win) {
+                    Some((*axis, true))
+                } else if second.is_leaf(win) {
+                    Some((*axis, false))
+                } else {
+                    first.parent_split(win).or_else(|| second.parent_split(win))
+                }
+            }
+        }
+    }
+
+    fn find_leaf_mut(&mut self, win: Window) -> Option<&mut Node> {
+        match self {
+            Node::Leaf(w) if *w == win => Some(self),
+            Node::Leaf(_) => None,
+            Node::Split { first, second, .. } => {
+                if let Some(n) = first.find_leaf_mut(win) {
+                    return Some(n);
+                }
+                second.find_leaf_mut(win)
+            }
+        }
+    }
+
+    fn parent_mut(&mut self, win: Window) -> Option<&mut Node> {
+        let is_parent = match self {
+            Node::Leaf(_) => return None,
+            Node::Split { first, second, .. } => first.is_leaf(win) || second.is_leaf(win),
+        };
+        if is_parent {
+            return Some(self);
+        }
+        match self {
+            Node::Leaf(_) => None,
+            Node::Split { first, second, .. } => {
+                if let Some(n) = first.parent_mut(win) {
+                    return Some(n);
+                }
+                second.parent_mut(win)
+            }
+        }
+    }
+
+    /// Remove the leaf `win`, its sibling takes the place of their parent
+    fn remove(self, win: Window) -> Option<Node> {
+        match self {
+            Node::Leaf(w) if w == win => None,
+            Node::Leaf(_) => Some(self),
+            Node::Split { axis, ratio, first, second } => match (first.remove(win), second.remove(win)) {
+                (Some(first), Some(second)) => Some(Node::Split {
+                    axis,
+                    ratio,
+                    first: Box::new(first),
+                    second: Box::new(second),
+                }),
+                (Some(n), None) | (None, Some(n)) => Some(n),
+                (None, None) => None,
+            },
+        }
+    }
+
+    /// Rotate clockwise by 90, 180 or 270 degrees
+    fn rotate(&mut self, degrees: u32) {
+        if let Node::Split { axis, ratio, first, second } = self {
+            // Turning clockwise by 90 degrees puts the top child on the right
+            let swap = degrees == 180
+                || (degrees == 90 && *axis == Axis::Y)
+                || (degrees == 270 && *axis == Axis::X);
+            if swap {
+                std::mem::swap(first, second);
+                *ratio = 1.0 - *ratio;
+            }
+            if degrees != 180 {
+                *axis = axis.other();
+            }
+            first.rotate(degrees);
+            second.rotate(degrees);
+        }
+    }
+
+    /// Mirror the children of every split along `flip_axis`
+    fn flip(&mut self, flip_axis: Axis) {
+        if let Node::Split { axis, ratio, first, second } = self {
+            if *axis == flip_axis {
+                std::mem::swap(first, second);
+                *ratio = 1.0 - *ratio;
+            }
+            first.flip(flip_axis);
+            second.flip(flip_axis);
+        }
+    }
+
+    /// Set the ratios so that every leaf gets the same area, returns the leaf count
+    fn balance(&mut self) -> usize {
+        match self {
+            Node::Leaf(_) => 1,
+            Node::Split { ratio, first, second, .. } => {
+                let n1 = first.balance();
+                let n2 = second.balance();
+                *ratio = n1 as f32 / (n1 + n2) as f32;
+                n1 + n2
+            }
+        }
+    }
+
+    /// Reset all ratios to the default
+    fn equalize(&mut self) {
+        if let Node::Split { ratio, first, second, .. } = self {
+            *ratio = DEFAULT_SPLIT_RATIO;
+            first.equalize();
+            second.equalize();
+        }
+    }
+}
+
+/**
+ * bspwm style binary space partitioning.
+ *
+ * The windows of a tag are the leaves of a binary tree. A new window splits the
+ * focused leaf in two, using either a pending preselection or the automatic
+ * scheme to decide the direction.
+ *
+ * Commands:
+ *   presel <west|east|north|south> [ratio] | presel cancel
+ *   node rotate <90|180|270> [root|parent]
+ *   node flip <horizontal|vertical> [root|parent]
+ *   node balance [root|parent]
+ *   node equalize [root|parent]
+ *   layout scheme <longest_side|alternate|spiral>
+ */
+pub struct Bsp {
+    root: Option<Node>,
+    scheme: Scheme,
+    presel: Option<Presel>,
+}
+
+impl Bsp {
+    pub fn new() -> Bsp {
+        Bsp {
+            root: None,
+            scheme: Scheme::LongestSide,
+            presel: None,
+        }
+    }
+
+    fn leaves(&self) -> Vec<Window> {
+        let mut leaves = Vec::new();
+        if let Some(root) = &self.root {
+            root.leaves(&mut leaves);
+        }
+        leaves
+    }
+
+    fn remove(&mut self, win: Window) {
+        self.root = self.root.take().and_then(|root| root.remove(win));
+        if self.presel.is_some_and(|p| p.win == win) {
+            self.presel = None;
+        }
+    }
+
+    /// Split the leaf `target` to make room for `win`
+    fn insert(&mut self, win: Window, target: Window, frame: &Rectangle) {
+        let root = match &mut self.root {
+            Some(root) => root,
+            None => {
+                self.root = Some(Node::Leaf(win));
+                return;
+            }
+        };
+
+        let (axis, new_first, ratio) = match self.presel.take() {
+            Some(p) if p.win == target => {
+                let axis = p.dir.axis();
+                let new_first = p.dir.is_backward();
+                // The preselected ratio is the share of the new window
+                let ratio = if new_first { p.ratio } else { 1.0 - p.ratio };
+                (axis, new_first, ratio)
+            }
+            presel => {
+                self.presel = presel;
+                let parent = root.parent_split(target);
+                match self.scheme {
+                    Scheme::LongestSide => {
+                        let mut regions = HashMap::new();
+                        root.regions(frame, &mut regions);
+                        let (_, _, w, h) = regions[&target].values();
+                        let axis = if w >= h { Axis::X } else { Axis::Y };
+                        (axis, false, DEFAULT_SPLIT_RATIO)
+                    }
+                    Scheme::Alternate => {
+                        let axis = parent.map_or(Axis::X, |(a, _)| a.other());
+                        (axis, false, DEFAULT_SPLIT_RATIO)
+                    }
+                    Scheme::Spiral => {
+                        let axis = parent.map_or(Axis::X, |(a, _)| a.other());
+                        // Turn clockwise: east, south, west, north, east...
+                        let new_first = parent.is_some_and(|(a, target_first)| (a == Axis::Y) != target_first);
+                        (axis, new_first, DEFAULT_SPLIT_RATIO)
+                    }
+                }
+            }
+        };
+
+        if let Some(leaf) = root.find_leaf_mut(target) {
+            let (first, second) = if new_first {
+                (Node::Leaf(win), Node::Leaf(target))
+            } else {
+                (Node::Leaf(target), Node::Leaf(win))
+            };
+            *leaf = Node::Split {
+                axis,
+                ratio,
+                first: Box::new(first),
+                second: Box::new(second),
+            };
+        }
+    }
+
+    /// The node an operation applies to: the whole tree, or the parent of the focused window
+    fn target_node(&mut self, target: Option<&str>, focused: Option<Window>) -> Result<&mut Node> {
+        let root = self.root.as_mut().context("node: empty tree")?;
+        match target.unwrap_or("root") {
+            "root" => Ok(root),
+            "parent" => {
+                let focused = focused.context("node: no focused window")?;
+                root.parent_mut(focused).context("node: the focused window has no parent")
+            }
+            t => Err(anyhow!("node: invalid target {}", t)),
+        }
+    }
+
+    fn node_command(&mut self, args: &[&str], focused: Option<Window>) -> Result<()> {
+        let op = *args.first().context("node: missing operation")?;
+        match op {
+            "rotate" => {
+                let degrees: u32 = args.get(1).context("node rotate: missing angle")?.parse()?;
+                if degrees != 90 && degrees != 180 && degrees != 270 {
+                    return Err(anyhow!("node rotate: invalid angle {}", degrees));
+                }
+                self.target_node(args.get(2).copied(), focused)?.rotate(degrees);
+            }
+            "flip" => {
+                // A horizontal flip mirrors top and bottom, so it swaps the Y splits
+                let axis = match *args.get(1).context("node flip: missing direction")? {
+                    "horizontal" => Axis::Y,
+                    "vertical" => Axis::X,
+                    d => return Err(anyhow!("node flip: invalid direction {}", d)),
+                };
+                self.target_node(args.get(2).copied(), focused)?.flip(axis);
+            }
+            "balance" => {
+                self.target_node(args.get(1).copied(), focused)?.balance();
+            }
+            "equalize" => self.target_node(args.get(1).copied(), focused)?.equalize(),
+            _ => return Err(anyhow!("node: unknown operation {}", op)),
+        }
+        Ok(())
+    }
+}
+
+impl Layout for Bsp {
+    fn name(&self) -> &'static str {
+        "bsp"
+    }
+
+    fn arrange(
+        &mut self,
+        frame: &Rectangle,
+        windows: &[Window],
+        _params: &LayoutParams,
+        focused: Option<Window>,
+    ) -> Vec<Rectangle> {
+        let leaves = self.leaves();
+        for &win in leaves.iter().filter(|w| !windows.contains(w)) {
+            self.remove(win);
+        }
+
+        // New windows split the focused leaf, further ones split the window
+        // inserted before them.
+        let mut leaves = self.leaves();
+        let mut target = focused.filter(|f| leaves.contains(f)).or_else(|| leaves.last().copied());
+        for &win in windows {
+            if !leaves.contains(&win) {
+                match target {
+                    Some(t) => self.insert(win, t, frame),
+                    None => self.root = Some(Node::Leaf(win)),
+                }
+                leaves.push(win);
+                target = Some(win);
+            }
+        }
+
+        let mut regions = HashMap::new();
+        if let Some(root) = &self.root {
+            root.regions(frame, &mut regions);
+        }
+        windows.iter().map(|w| regions[w]).collect()
+    }
+
+    fn status(&self, _windows: &[Window], _focused: Option<Window>) -> String {
+        let count = self.root.as_ref().map_or(0, |r| r.leaf_count());
+        match self.presel {
+            Some(p) => format!("{} {} presel {:?}", self.name(), count, p.dir).to_lowercase(),
+            None => format!("{} {}", self.name(), count),
+        }
+    }
+
+    fn swap_windows(&mut self, a: Window, b: Window) {
+        if let Some(root) = self.root.as_mut() {
+            root.swap_leaves(a, b);
+        }
+        if let Some(presel) = self.presel.as_mut() {
+            swap_id(&mut presel.win, a, b);
+        }
+    }
+
+    fn command(&mut self, args: &[&str], focused: Option<Window>) -> Result<Option<Window>> {
+        match args {
+            ["presel", "cancel"] => self.presel = None,
+            ["presel", dir, rest @ ..] => {
+                let win = focused.context("presel: no focused window")?;
+                let ratio = match rest.first() {
+                    Some(r) => r.parse::<f32>()?,
+                    None => DEFAULT_SPLIT_RATIO,
+                };
+                self.presel = Some(Presel {
+                    win,
+                    dir: dir.parse()?,
+                    ratio: ratio.clamp(MIN_MAIN_RATIO, MAX_MAIN_RATIO),
+                });
+            }
+            ["node", rest @ ..] => self.node_command(rest, focused)?,
+            ["scheme", scheme] => {
+                self.scheme = match *scheme {
+                    "longest_side" => Scheme::LongestSide,
+                    "alternate" => Scheme::Alternate,
+                    "spiral" => Scheme::Spiral,
+                    _ => return Err(anyhow!("scheme: invalid scheme {}", scheme)),
+                }
+            }
+            _ => return Err(anyhow!("bsp: unsupported command {}", args.join(" "))),
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: LayoutParams = LayoutParams { nmaster: 1, main_ratio: 0.5 };
+
+    fn arrange(bsp: &mut Bsp, windows: &[Window], focused: Window) -> Vec<(i32, i32, u32, u32)> {
+        let frame = Rectangle::new(0, 0, 120, 60);
+        bsp.arrange(&frame, windows, &PARAMS, Some(focused))
+            .iter()
+            .map(Rectangle::values)
+            .collect()
+    }
+
+    #[test]
+    fn insert_splits_the_longest_side_of_the_focused_window() {
+        let mut bsp = Bsp::new();
+        assert_eq!(arrange(&mut bsp, &[1], 1), vec![(0, 0, 120, 60)]);
+        assert_eq!(arrange(&mut bsp, &[1, 2], 1), vec![(0, 0, 60, 60), (60, 0, 60, 60)]);
+        // 2 is square, so it is split side by side again
+        assert_eq!(
+            arrange(&mut bsp, &[1, 2, 3], 2),
+            vec![(0, 0, 60, 60), (60, 0, 30, 60), (90, 0, 30, 60)]
+        );
+        // 3 is taller than wide
+        assert_eq!(
+            arrange(&mut bsp, &[1, 2, 3, 4], 3),
+            vec![(0, 0, 60, 60), (60, 0, 30, 60), (90, 0, 30, 30), (90, 30, 30, 30)]
+        );
+    }
+
+    #[test]
+    fn presel_sets_the_side_and_share_of_the_new_window() {
+        let mut bsp = Bsp::new();
+        arrange(&mut bsp, &[1], 1);
+        bsp.command(&["presel", "west", "0.25"], Some(1)).unwrap();
+        assert_eq!(arrange(&mut bsp, &[1, 2], 1), vec![(30, 0, 90, 60), (0, 0, 30, 60)]);
+        assert!(bsp.presel.is_none());
+
+        bsp.command(&["presel", "south"], Some(1)).unwrap();
+        assert_eq!(
+            arrange(&mut bsp, &[1, 2, 3], 1),
+            vec![(30, 0, 90, 30), (0, 0, 30, 60), (30, 30, 90, 30)]
+        );
+    }
+
+    #[test]
+    fn removed_window_leaves_its_place_to_the_sibling() {
+        let mut bsp = Bsp::new();
+        arrange(&mut bsp, &[1, 2, 3], 1);
+        assert_eq!(arrange(&mut bsp, &[1, 3], 1), vec![(0, 0, 60, 60), (60, 0, 60, 60)]);
+        assert_eq!(arrange(&mut bsp, &[3], 3), vec![(0, 0, 120, 60)]);
+        assert_eq!(arrange(&mut bsp, &[], 3), vec![]);
+        assert!(bsp.root.is_none());
+    }
+
+    #[test]
+    fn removing_a_preselected_window_cancels_the_presel() {
+        let mut bsp = Bsp::new();
+        arrange(&mut bsp, &[1, 2], 1);
+        bsp.command(&["presel", "north"], Some(2)).unwrap();
+        arrange(&mut bsp, &[1], 1);
+        assert!(bsp.presel.is_none());
+    }
+
+    #[test]
+    fn rotate_turns_the_tree_clockwise() {
+        let mut bsp = Bsp::new();
+        arrange(&mut bsp, &[1, 2], 1);
+        bsp.command(&["node", "rotate", "90"], None).unwrap();
+        assert_eq!(arrange(&mut bsp, &[1, 2], 1), vec![(0, 0, 120, 30), (0, 30, 120, 30)]);
+        bsp.command(&["node", "rotate", "90"], None).unwrap();
+        assert_eq!(arrange(&mut bsp, &[1, 2], 1), vec![(60, 0, 60, 60), (0, 0, 60, 60)]);
+        bsp.command(&["node", "rotate", "180"], None).unwrap();
+        assert_eq!(arrange(&mut bsp, &[1, 2], 1), vec![(0, 0, 60, 60), (60, 0, 60, 60)]);
+        bsp.command(&["node", "rotate", "270"], None).unwrap();
+        assert_eq!(arrange(&mut bsp, &[1, 2], 1), vec![(0, 30, 120, 30), (0, 0, 120, 30)]);
+        assert!(bsp.command(&["node", "rotate", "45"], None).is_err());
+    }
+
+    #[test]
+    fn rotate_keeps_uneven_ratios_with_their_window() {
+        let mut bsp = Bsp::new();
+        arrange(&mut bsp, &[1], 1);
+        bsp.command(&["presel", "east", "0.25"], Some(1)).unwrap();
+        assert_eq!(arrange(&mut bsp, &[1, 2], 1), vec![(0, 0, 90, 60), (90, 0, 30, 60)]);
+        bsp.command(&["node", "rotate", "90"], None).unwrap();
+        assert_eq!(arrange(&mut bsp, &[1, 2], 1), vec![(0, 0, 120, 45), (0, 45, 120, 15)]);
+    }
+
+    #[test]
+    fn flip_mirrors_the_splits_along_one_axis() {
+        let mut bsp = Bsp::new();
+        arrange(&mut bsp, &[1, 2, 3], 2);
+        // 1 and 2 side by side, then 3 on top of 4 on the right
+        arrange(&mut bsp, &[1, 2, 3, 4], 3);
+        bsp.command(&["node", "flip", "horizontal"], None).unwrap();
+        assert_eq!(
+            arrange(&mut bsp, &[1, 2, 3, 4], 3),
+            vec![(0, 0, 60, 60), (60, 0, 30, 60), (90, 30, 30, 30), (90, 0, 30, 30)]
+        );
+        bsp.command(&["node", "flip", "vertical"], None).unwrap();
+        assert_eq!(
+            arrange(&mut bsp, &[1, 2, 3, 4], 3),
+            vec![(60, 0, 60, 60), (30, 0, 30, 60), (0, 30, 30, 30), (0, 0, 30, 30)]
+        );
+    }
+
+    #[test]
+    fn flip_of_the_parent_leaves_the_rest_of_the_tree() {
+        let mut bsp = Bsp::new();
+        arrange(&mut bsp, &[1, 2, 3], 2);
+        bsp.command(&["node", "flip", "vertical", "parent"], Some(3)).unwrap();
+        assert_eq!(
+            arrange(&mut bsp, &[1, 2, 3], 2),
+            vec![(0, 0, 60, 60), (90, 0, 30, 60), (60, 0, 30, 60)]
+        );
+        assert!(bsp.command(&["node", "flip", "vertical", "parent"], None).is_err());
+    }
+
+    #[test]
+    fn balance_gives_every_window_the_same_area() {
+        let mut bsp = Bsp::new();
+        arrange(&mut bsp, &[1, 2, 3], 2);
+        bsp.command(&["node", "balance"], None).unwrap();
+        assert_eq!(
+            arrange(&mut bsp, &[1, 2, 3], 2),
+            vec![(0, 0, 40, 60), (40, 0, 40, 60), (80, 0, 40, 60)]
+        );
+        bsp.command(&["node", "equalize"], None).unwrap();
+        assert_eq!(
+            arrange(&mut bsp, &[1, 2, 3], 2),
+            vec![(0, 0, 60, 60), (60, 0, 30, 60), (90, 0, 30, 60)]
+        );
+    }
+
+    #[test]
+    fn balance_counts_the_leaves() {
+        let mut root = Node::Split {
+            axis: Axis::X,
+            ratio: 0.5,
+            first: Box::new(Node::Leaf(1)),
+            second: Box::new(Node::Split {
+                axis: Axis::Y,
+                ratio: 0.5,
+                first: Box::new(Node::Leaf(2)),
+                second: Box::new(Node::Split {
+                    axis: Axis::X,
+                    ratio: 0.5,
+                    first: Box::new(Node::Leaf(3)),
+                    second: Box::new(Node::Leaf(4)),
+                }),
+            }),
+        };
+        assert_eq!(root.balance(), 4);
+        match root {
+            Node::Split { ratio, second, .. } => {
+                assert_eq!(ratio, 0.25);
+                match *second {
+                    Node::Split { ratio, .. } => assert!((ratio - 1.0 / 3.0).abs() < 1e-6),
+                    Node::Leaf(_) => panic!("expected a split"),
+                }
+            }
+            Node::Leaf(_) => panic!("expected a split"),
+        }
+    }
+}
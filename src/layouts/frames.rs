@@ -0,0 +1,521 @@
+use std::str::FromStr;
+
+use anyhow::{Result, Context, anyhow};
+use xcb::Window;
+
+use crate::{
+    layouts::{
+        Layout, LayoutParams, Direction, Axis, Columns, Rows, Monocle, Grid,
+        split_region, find_in_direction, swap_id,
+    },
+    xconnection::Rectangle,
+};
+
+const DEFAULT_SPLIT_FRACTION: f32 = 0.5;
+const MIN_SPLIT_FRACTION: f32 = 0.1;
+const MAX_SPLIT_FRACTION: f32 = 0.9;
+
+/// How the windows of a leaf frame are arranged
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum FrameLayout {
+    /// Stacked from top to bottom
+    Vertical,
+    /// Side by side
+    Horizontal,
+    /// All windows take the whole frame
+    Max,
+    Grid,
+}
+
+impl FromStr for FrameLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<FrameLayout> {
+        match s {
+            "vertical" => Ok(FrameLayout::Vertical),
+            "horizontal" => Ok(FrameLayout::Horizontal),
+            "max" => Ok(FrameLayout::Max),
+            "grid" => Ok(FrameLayout::Grid),
+            _ => Err(anyhow!("invalid frame layout {}", s)),
+        }
+    }
+}
+
+impl FrameLayout {
+    fn name(self) -> &'static str {
+        match self {
+            FrameLayout::Vertical => "vertical",
+            FrameLayout::Horizontal => "horizontal",
+            FrameLayout::Max => "max",
+            FrameLayout::Grid => "grid",
+        }
+    }
+
+    fn arrange(self, frame: &Rectangle, windows: &[Window], params: &LayoutParams) -> Vec<Rectangle> {
+        match self {
+            FrameLayout::Vertical => Rows.arrange(frame, windows, params, None),
+            FrameLayout::Horizontal => Columns.arrange(frame, windows, params, None),
+            FrameLayout::Max => Monocle.arrange(frame, windows, params, None),
+            FrameLayout::Grid => Grid.arrange(frame, windows, params, None),
+        }
+    }
+}
+
+/// Path from the root to a frame: false to go to the first child, true for the second
+type FramePath = Vec<bool>;
+
+#[derive(Debug)]
+enum Frame {
+    Leaf {
+        windows: Vec<Window>,
+        layout: FrameLayout,
+        /// The window of this frame that had the focus last
+        selected: Option<Window>,
+    },
+    Split {
+        axis: Axis,
+        /// Fraction of the frame given to the first child
+        fraction: f32,
+        first: Box<Frame>,
+        second: Box<Frame>,
+    },
+}
+
+impl Frame {
+    fn empty(layout: FrameLayout) -> Frame {
+        Frame::Leaf {
+            windows: Vec::new(),
+            layout,
+            selected: None,
+        }
+    }
+
+    fn get(&self, path: &[bool]) -> Option<&Frame> {
+        match (path.split_first(), self) {
+            (None, _) => Some(self),
+            (Some((&second, rest)), Frame::Split { first: f, second: s, .. }) => {
+                if second { s.get(rest) } else { f.get(rest) }
+            }
+            (Some(_), Frame::Leaf { .. }) => None,
+        }
+    }
+
+    fn get_mut(&mut self, path: &[bool]) -> Option<&mut Frame> {
+        match (path.split_first(), self) {
+            (None, frame) => Some(frame),
+            (Some((&second, rest)), Frame::Split { first: f, second: s, .. }) => {
+                if second { s.get_mut(rest) } else { f.get_mut(rest) }
+            }
+            (Some(_), Frame::Leaf { .. }) => None,
+        }
+    }
+
+    /// Paths of all leaf frames along with their regions, in tree order
+    fn leaves(&self, region: &Rectangle, path: &mut FramePath, out: &mut Vec<(FramePath, Rectangle)>) {
+        match self {
+            Frame::Leaf { .. } => out.push((path.clone(), *region)),
+            Frame::Split { axis, fraction, first, second } => {
+                let (r1, r2) = split_region(region, *axis, *fraction);
+                path.push(false);
+                first.leaves(&r1, path, out);
+                path.pop();
+                path.push(true);
+                second.leaves(&r2, path, out);
+                path.pop();
+            }
+        }
+    }
+
+    fn windows(&self, out: &mut Vec<Window>) {
+        match self {
+            Frame::Leaf { windows, .. } => out.extend(windows),
+            Frame::Split { first, second, .. } => {
+                first.windows(out);
+                second.windows(out);
+            }
+        }
+    }
+
+    fn retain_windows(&mut self, keep: &[Window]) {
+        match self {
+            Frame::Leaf { windows, selected, .. } => {
+                windows.retain(|w| keep.contains(w));
+                if selected.is_some_and(|s| !keep.contains(&s)) {
+                    *selected = None;
+                }
+            }
+            Frame::Split { first, second, .. } => {
+                first.retain_windows(keep);
+                second.retain_windows(keep);
+            }
+        }
+    }
+
+    fn swap_windows(&mut self, a: Window, b: Window) {
+        match self {
+            Frame::Leaf { windows, selected, .. } => {
+                windows.iter_mut().for_each(|w| swap_id(w, a, b));
+                if let Some(s) = selected {
+                    swap_id(s, a, b);
+                }
+            }
+            Frame::Split { first, second, .. } => {
+                first.swap_windows(a, b);
+                second.swap_windows(a, b);
+            }
+        }
+    }
+
+    fn find_window(&self, win: Window, path: &mut FramePath) -> bool {
+        match self {
+            Frame::Leaf { windows, .. } => windows.contains(&win),
+            Frame::Split { first, second, .. } => {
+                path.push(false);
+                if first.find_window(win, path) {
+                    return true;
+                }
+                path.pop();
+                path.push(true);
+                if second.find_window(win, path) {
+                    return true;
+                }
+                path.pop();
+                false
+            }
+        }
+    }
+}
+
+/**
+ * herbstluftwm style manual tiling.
+ *
+ * A tag is a tree of frames that is only changed on request: the focused frame
+ * can be split in two or removed. Every leaf frame arranges its own windows
+ * with a simple layout, and new windows are put in the focused frame.
+ *
+ * Commands:
+ *   split <top|bottom|left|right|vertical|horizontal> [fraction]
+ *   remove
+ *   focus_frame <left|right|up|down>
+ *   layout frame_layout <vertical|horizontal|max|grid>
+ */
+pub struct Frames {
+    root: Frame,
+    focus: FramePath,
+    /// The focused window seen by the last call to arrange
+    last_focused: Option<Window>,
+    /// The region used by the last call to arrange, for directional commands
+    region: Rectangle,
+}
+
+impl Frames {
+    pub fn new() -> Frames {
+        Frames {
+            root: Frame::empty(FrameLayout::Vertical),
+            focus: Vec::new(),
+            last_focused: None,
+            region: Rectangle::new(0, 0, 1, 1),
+        }
+    }
+
+    fn focused_frame(&mut self) -> &mut Frame {
+        // The focus path is always kept valid, fall back on the root just in case
+        if self.root.get(&self.focus).is_none() {
+            self.focus.clear();
+        }
+        self.root.get_mut(&self.focus).unwrap()
+    }
+
+    /// The first leaf below the frame at `path`
+    fn first_leaf(&self, path: &[bool]) -> FramePath {
+        let mut path = path.to_vec();
+        while let Some(Frame::Split { .. }) = self.root.get(&path) {
+            path.push(false);
+        }
+        path
+    }
+
+    /// The window to focus when moving to the frame at `path`
+    fn selected_window(&self, path: &[bool]) -> Option<Window> {
+        match self.root.get(path) {
+            Some(Frame::Leaf { windows, selected, .. }) => selected.or_else(|| windows.first().copied()),
+            _ => None,
+        }
+    }
+
+    fn split(&mut self, args: &[&str]) -> Result<()> {
+        let align = *args.first().context("split: missing alignment")?;
+        let (axis, new_first) = match align {
+            "vertical" => (Axis::Y, false),
+            "horizontal" => (Axis::X, false),
+            // The new frame goes on that side
+            _ => match align.parse::<Direction>() {
+                Ok(dir) => (dir.axis(), dir.is_backward()),
+                Err(_) => return Err(anyhow!("split: invalid alignment {}", align)),
+            },
+        };
+        let fraction = match args.get(1) {
+            Some(f) => f.parse::<f32>()?.clamp(MIN_SPLIT_FRACTION, MAX_SPLIT_FRACTION),
+            None => DEFAULT_SPLIT_FRACTION,
+        };
+
+        let frame = self.focused_frame();
+        let layout = match frame {
+            Frame::Leaf { layout, .. } => *layout,
+            Frame::Split { .. } => FrameLayout::Vertical,
+        };
+        let old = std::mem::replace(frame, Frame::empty(layout));
+        let (first, second) = if new_first {
+            (Frame::empty(layout), old)
+        } else {
+            (old, Frame::empty(layout))
+        };
+        *frame = Frame::Split {
+            axis,
+            fraction,
+            first: Box::new(first),
+            second: Box::new(second),
+        };
+        // The focus stays with the windows of the old frame
+        self.focus.push(new_first);
+        Ok(())
+    }
+
+    /// Remove the focused frame, its windows go to the neighbouring frame
+    fn remove(&mut self) -> Result<()> {
+        let side = self.focus.pop().context("remove: can't remove the root frame")?;
+        let parent = self.root.get_mut(&self.focus).context("remove: invalid frame")?;
+        let (removed, kept) = match std::mem::replace(parent, Frame::empty(FrameLayout::Vertical)) {
+            Frame::Split { first, second, .. } => {
+                if side { (second, first) } else { (first, second) }
+            }
+            leaf => {
+                *parent = leaf;
+                return Err(anyhow!("remove: invalid frame"));
+            }
+        };
+        *parent = *kept;
+
+        let mut orphans = Vec::new();
+        removed.windows(&mut orphans);
+        self.focus = self.first_leaf(&self.focus);
+        if let Frame::Leaf { windows, .. } = self.focused_frame() {
+            windows.extend(orphans);
+        }
+        Ok(())
+    }
+
+    fn focus_frame(&mut self, args: &[&str]) -> Result<Option<Window>> {
+        let dir: Direction = args.first().context("focus_frame: missing direction")?.parse()?;
+        let mut leaves = Vec::new();
+        self.root.leaves(&self.region, &mut Vec::new(), &mut leaves);
+        let current = leaves
+            .iter()
+            .find(|(path, _)| *path == self.focus)
+            .map(|(_, r)| *r)
+            .context("focus_frame: invalid focused frame")?;
+        let regions: Vec<Rectangle> = leaves.iter().map(|(_, r)| *r).collect();
+        let target = find_in_direction(&current, &regions, dir).context("focus_frame: no frame in that direction")?;
+        self.focus = leaves[target].0.clone();
+        Ok(self.selected_window(&self.focus))
+    }
+}
+
+impl Layout for Frames {
+    fn name(&self) -> &'static str {
+        "frames"
+    }
+
+    fn arrange(
+        &mut self,
+        frame: &Rectangle,
+        windows: &[Window],
+        params: &LayoutParams,
+        focused: Option<Window>,
+    ) -> Vec<Rectangle> {
+        self.region = *frame;
+        self.root.retain_windows(windows);
+
+        // The focused frame follows the focus when it moves to another window,
+        // but stays put (possibly on an empty frame) while the focus doesn't change
+        if focused != self.last_focused {
+            self.last_focused = focused;
+            if let Some(f) = focused {
+                let mut path = Vec::new();
+                if self.root.find_window(f, &mut path) {
+                    self.focus = path;
+                    if let Frame::Leaf { selected, .. } = self.focused_frame() {
+                        *selected = Some(f);
+                    }
+                }
+            }
+        }
+
+        let mut known = Vec::new();
+        self.root.windows(&mut known);
+        let new: Vec<Window> = windows.iter().filter(|w| !known.contains(w)).copied().collect();
+        if let Frame::Leaf { windows, .. } = self.focused_frame() {
+            windows.extend(new);
+        }
+
+        let mut leaves = Vec::new();
+        self.root.leaves(frame, &mut Vec::new(), &mut leaves);
+        let mut regions = Vec::new();
+        for (path, region) in leaves {
+            if let Some(Frame::Leaf { windows: w, layout, .. }) = self.root.get(&path) {
+                regions.extend(w.iter().copied().zip(layout.arrange(&region, w, params)));
+            }
+        }
+        windows
+            .iter()
+            .map(|w| regions.iter().find(|(r, _)| r == w).map_or(*frame, |(_, r)| *r))
+            .collect()
+    }
+
+    fn status(&self, _windows: &[Window], _focused: Option<Window>) -> String {
+        let mut leaves = Vec::new();
+        self.root.leaves(&self.region, &mut Vec::new(), &mut leaves);
+        let index = leaves.iter().position(|(path, _)| *path == self.focus).map_or(0, |i| i + 1);
+        let layout = match self.root.get(&self.focus) {
+            Some(Frame::Leaf { layout, .. }) => layout.name(),
+            _ => "",
+        };
+        format!("{} {}/{} {}", self.name(), index, leaves.len(), layout)
+    }
+
+    fn swap_windows(&mut self, a: Window, b: Window) {
+        self.root.swap_windows(a, b);
+    }
+
+    fn command(&mut self, args: &[&str], focused: Option<Window>) -> Result<Option<Window>> {
+        // Commands act on the focused frame, which must not be moved back to the
+        // focused window by the next arrange
+        self.last_focused = focused;
+        match args {
+            ["split", rest @ ..] => self.split(rest)?,
+            ["remove"] => {
+                self.remove()?;
+                return Ok(self.selected_window(&self.focus));
+            }
+            ["focus_frame", rest @ ..] => return self.focus_frame(rest),
+            ["frame_layout", name] => {
+                let new_layout: FrameLayout = name.parse()?;
+                if let Frame::Leaf { layout, .. } = self.focused_frame() {
+                    *layout = new_layout;
+                }
+            }
+            _ => return Err(anyhow!("frames: unsupported command {}", args.join(" "))),
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: LayoutParams = LayoutParams { nmaster: 1, main_ratio: 0.5 };
+
+    fn arrange(frames: &mut Frames, windows: &[Window], focused: Option<Window>) -> Vec<(i32, i32, u32, u32)> {
+        let frame = Rectangle::new(0, 0, 120, 60);
+        frames.arrange(&frame, windows, &PARAMS, focused)
+            .iter()
+            .map(Rectangle::values)
+            .collect()
+    }
+
+    fn leaves(frames: &Frames) -> Vec<(FramePath, (i32, i32, u32, u32))> {
+        let mut leaves = Vec::new();
+        frames.root.leaves(&frames.region, &mut Vec::new(), &mut leaves);
+        leaves.into_iter().map(|(path, r)| (path, r.values())).collect()
+    }
+
+    #[test]
+    fn split_keeps_the_windows_in_the_focused_frame() {
+        let mut frames = Frames::new();
+        arrange(&mut frames, &[1, 2], Some(1));
+        frames.command(&["split", "horizontal"], Some(1)).unwrap();
+        assert_eq!(
+            arrange(&mut frames, &[1, 2], Some(1)),
+            vec![(0, 0, 60, 30), (0, 30, 60, 30)]
+        );
+        assert_eq!(
+            leaves(&frames),
+            vec![(vec![false], (0, 0, 60, 60)), (vec![true], (60, 0, 60, 60))]
+        );
+        assert_eq!(frames.focus, vec![false]);
+    }
+
+    #[test]
+    fn split_towards_a_direction_puts_the_new_frame_there() {
+        let mut frames = Frames::new();
+        arrange(&mut frames, &[1], Some(1));
+        frames.command(&["split", "top", "0.25"], Some(1)).unwrap();
+        assert_eq!(arrange(&mut frames, &[1], Some(1)), vec![(0, 15, 120, 45)]);
+        assert_eq!(frames.focus, vec![true]);
+
+        frames.command(&["split", "west"], Some(1)).unwrap();
+        assert_eq!(arrange(&mut frames, &[1], Some(1)), vec![(60, 15, 60, 45)]);
+        assert_eq!(frames.focus, vec![true, true]);
+        assert!(frames.command(&["split", "diagonal"], Some(1)).is_err());
+    }
+
+    #[test]
+    fn new_windows_go_to_the_focused_frame() {
+        let mut frames = Frames::new();
+        arrange(&mut frames, &[1], Some(1));
+        frames.command(&["split", "vertical"], Some(1)).unwrap();
+        assert_eq!(frames.command(&["focus_frame", "south"], Some(1)).unwrap(), None);
+        assert_eq!(
+            arrange(&mut frames, &[1, 2], Some(1)),
+            vec![(0, 0, 120, 30), (0, 30, 120, 30)]
+        );
+        assert_eq!(frames.command(&["focus_frame", "north"], Some(1)).unwrap(), Some(1));
+        assert!(frames.command(&["focus_frame", "north"], Some(1)).is_err());
+    }
+
+    #[test]
+    fn focus_follows_the_focused_window() {
+        let mut frames = Frames::new();
+        arrange(&mut frames, &[1], Some(1));
+        frames.command(&["split", "horizontal"], Some(1)).unwrap();
+        frames.command(&["focus_frame", "east"], Some(1)).unwrap();
+        // New windows go to the focused frame, which stays put until the focus moves
+        arrange(&mut frames, &[1, 2], Some(1));
+        assert_eq!(frames.focus, vec![true]);
+        arrange(&mut frames, &[1, 2], Some(1));
+        assert_eq!(frames.focus, vec![true]);
+        arrange(&mut frames, &[1, 2], Some(2));
+        assert_eq!(frames.focus, vec![true]);
+        arrange(&mut frames, &[1, 2], Some(1));
+        assert_eq!(frames.focus, vec![false]);
+    }
+
+    #[test]
+    fn remove_moves_the_windows_to_the_sibling() {
+        let mut frames = Frames::new();
+        arrange(&mut frames, &[1], Some(1));
+        frames.command(&["split", "horizontal"], Some(1)).unwrap();
+        frames.command(&["focus_frame", "east"], Some(1)).unwrap();
+        arrange(&mut frames, &[1, 2], Some(1));
+        frames.command(&["split", "vertical"], Some(1)).unwrap();
+
+        // Removing the empty frame below 2 gives its space back to 2
+        frames.command(&["focus_frame", "south"], Some(1)).unwrap();
+        assert_eq!(frames.command(&["remove"], Some(1)).unwrap(), Some(2));
+        assert_eq!(frames.focus, vec![true]);
+        assert_eq!(
+            arrange(&mut frames, &[1, 2], Some(1)),
+            vec![(0, 0, 60, 60), (60, 0, 60, 60)]
+        );
+
+        // Removing the frame of 2 moves it next to 1
+        assert_eq!(frames.command(&["remove"], Some(1)).unwrap(), Some(1));
+        assert!(frames.focus.is_empty());
+        assert_eq!(
+            arrange(&mut frames, &[1, 2], Some(1)),
+            vec![(0, 0, 120, 30), (0, 30, 120, 30)]
+        );
+        assert!(frames.command(&["remove"], Some(1)).is_err());
+    }
+}
@@ -16,6 +16,9 @@ mod events;
 mod workspace;
 mod view;
 mod tag;
+mod layouts;
+mod scratchpad;
+mod rules;
 
 pub use xconnection::XcbConnection;
 pub use wm::WindowManager;
@@ -0,0 +1,458 @@
+use anyhow::{Result, Context, anyhow};
+use regex::Regex;
+use std::fmt;
+
+use crate::{
+    xconnection::Rectangle,
+    wm::parse_switch,
+};
+
+/**
+ * Rules decide how new windows are handled, in the spirit of herbstluftwm's
+ * `hc rule`. A rule is a list of conditions and consequences:
+ *
+ *     wontdo rule add class=Firefox title~'.*YouTube.*' tag=3 floating=on
+ *     wontdo rule add windowtype~'NOTIFICATION|DOCK|DESKTOP' manage=off
+ *
+ * `property=value` matches the exact value and `property~regex` the whole
+ * value against a regex. Values can be quoted to hold spaces, and since the
+ * shell already removes quotes, a word without = or ~ continues the value
+ * before it: `title~Mozilla Firefox` matches the whole title.
+ *
+ * All conditions of a rule must match for its consequences to apply. Rules
+ * are applied in the order they were added, so a later rule overrides the
+ * consequences of an earlier one.
+ */
+pub struct Rule {
+    id: usize,
+    conditions: Vec<Condition>,
+    consequences: Vec<Consequence>,
+}
+
+/// The window properties conditions are matched against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Property {
+    Class,
+    Instance,
+    Title,
+    /// _NET_WM_WINDOW_TYPE, with or without the _NET_WM_WINDOW_TYPE_ prefix
+    WindowType,
+    /// WM_WINDOW_ROLE
+    WindowRole,
+    /// Whether the window is transient for another one, `on` or `off`
+    TransientFor,
+}
+
+impl Property {
+    fn parse(name: &str) -> Result<Property> {
+        match name {
+            "class" => Ok(Property::Class),
+            "instance" => Ok(Property::Instance),
+            "title" => Ok(Property::Title),
+            "windowtype" => Ok(Property::WindowType),
+            "windowrole" => Ok(Property::WindowRole),
+            "transient_for" => Ok(Property::TransientFor),
+            _ => Err(anyhow!("rule: unknown property {}", name)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Property::Class => "class",
+            Property::Instance => "instance",
+            Property::Title => "title",
+            Property::WindowType => "windowtype",
+            Property::WindowRole => "windowrole",
+            Property::TransientFor => "transient_for",
+        }
+    }
+}
+
+enum Pattern {
+    Exact(String),
+    Regex(Regex),
+}
+
+struct Condition {
+    property: Property,
+    pattern: Pattern,
+}
+
+impl Condition {
+    fn matches(&self, window: &WindowProperties) -> bool {
+        let full_types: Vec<String>;
+        let values: Vec<&str> = match self.property {
+            Property::Class => vec![&window.class],
+            Property::Instance => vec![&window.instance],
+            Property::Title => vec![&window.title],
+            Property::WindowType => {
+                // Both DIALOG and _NET_WM_WINDOW_TYPE_DIALOG can be matched
+                full_types = window
+                    .window_types
+                    .iter()
+                    .map(|t| format!("_NET_WM_WINDOW_TYPE_{}", t))
+                    .collect();
+                window.window_types.iter().chain(&full_types).map(String::as_str).collect()
+            }
+            Property::WindowRole => vec![&window.window_role],
+            Property::TransientFor => vec![if window.transient { "on" } else { "off" }],
+        };
+        values.into_iter().any(|value| match &self.pattern {
+            Pattern::Exact(exact) => value == exact,
+            Pattern::Regex(regex) => regex.is_match(value),
+        })
+    }
+}
+
+enum Consequence {
+    Tag(String),
+    Monitor(usize),
+    Floating(bool),
+    Fullscreen(bool),
+    Focus(bool),
+    Geometry(Rectangle),
+    Manage(bool),
+}
+
+impl Consequence {
+    fn parse(name: &str, value: &str) -> Result<Consequence> {
+        Ok(match name {
+            "tag" => Consequence::Tag(value.to_string()),
+            "monitor" => Consequence::Monitor(value.parse()?),
+            "floating" => Consequence::Floating(parse_switch(value, false)?),
+            "fullscreen" => Consequence::Fullscreen(parse_switch(value, false)?),
+            "focus" => Consequence::Focus(parse_switch(value, false)?),
+            "geometry" => Consequence::Geometry(parse_geometry(value)?),
+            "manage" => Consequence::Manage(parse_switch(value, false)?),
+            _ => return Err(anyhow!("rule: unknown consequence {}", name)),
+        })
+    }
+}
+
+/// What is known about a new window when the rules are applied
+#[derive(Debug, Default)]
+pub struct WindowProperties {
+    pub class: String,
+    pub instance: String,
+    pub title: String,
+    pub window_types: Vec<String>,
+    pub window_role: String,
+    pub transient: bool,
+}
+
+/// The combined consequences of the rules that matched a window.
+/// None leaves the decision to the window manager.
+#[derive(Debug, Default)]
+pub struct Outcome {
+    pub tag: Option<String>,
+    pub monitor: Option<usize>,
+    pub floating: Option<bool>,
+    pub fullscreen: Option<bool>,
+    pub focus: Option<bool>,
+    pub geometry: Option<Rectangle>,
+    pub manage: Option<bool>,
+}
+
+impl Rule {
+    /// Parse the arguments of `rule add`
+    pub fn parse(id: usize, args: &str) -> Result<Rule> {
+        let mut conditions = Vec::new();
+        let mut consequences = Vec::new();
+        for arg in split_args(args)? {
+            let split = arg
+                .find(['=', '~'])
+                .with_context(|| format!("rule: expected name=value or name~regex, got {}", arg))?;
+            let (name, value) = (&arg[..split], &arg[split + 1..]);
+            let is_regex = arg[split..].starts_with('~');
+            match Property::parse(name) {
+                Ok(property) => {
+                    let pattern = if is_regex {
+                        // Match the whole value, like an exact match does
+                        let regex = Regex::new(&format!("^(?:{})$", value))
+                            .with_context(|| format!("rule: invalid regex {}", value))?;
+                        Pattern::Regex(regex)
+                    } else {
+                        Pattern::Exact(value.to_string())
+                    };
+                    conditions.push(Condition { property, pattern });
+                }
+                Err(_) if !is_regex => consequences.push(Consequence::parse(name, value)?),
+                Err(e) => return Err(e),
+            }
+        }
+        if consequences.is_empty() {
+            return Err(anyhow!("rule: no consequence given"));
+        }
+        Ok(Rule {
+            id,
+            conditions,
+            consequences,
+        })
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// A rule without conditions matches every window
+    pub fn matches(&self, window: &WindowProperties) -> bool {
+        self.conditions.iter().all(|c| c.matches(window))
+    }
+
+    /// Add the consequences of this rule to an outcome
+    pub fn apply(&self, outcome: &mut Outcome) {
+        for consequence in &self.consequences {
+            match consequence {
+                Consequence::Tag(tag) => outcome.tag = Some(tag.clone()),
+                Consequence::Monitor(monitor) => outcome.monitor = Some(*monitor),
+                Consequence::Floating(on) => outcome.floating = Some(*on),
+                Consequence::Fullscreen(on) => outcome.fullscreen = Some(*on),
+                Consequence::Focus(on) => outcome.focus = Some(*on),
+                Consequence::Geometry(geometry) => outcome.geometry = Some(*geometry),
+                Consequence::Manage(on) => outcome.manage = Some(*on),
+            }
+        }
+    }
+}
+
+/// Formats the rule the way it can be given to `rule add`
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:", self.id)?;
+        for condition in &self.conditions {
+            match &condition.pattern {
+                Pattern::Exact(value) if value.contains(char::is_whitespace) => {
+                    write!(f, " {}='{}'", condition.property.name(), value)?
+                }
+                Pattern::Exact(value) => write!(f, " {}={}", condition.property.name(), value)?,
+                // Leave out the anchors added when parsing
+                Pattern::Regex(regex) => {
+                    let regex = regex.as_str();
+                    write!(f, " {}~'{}'", condition.property.name(), &regex[4..regex.len() - 2])?
+                }
+            }
+        }
+        for consequence in &self.consequences {
+            match consequence {
+                Consequence::Tag(tag) if tag.contains(char::is_whitespace) => write!(f, " tag='{}'", tag)?,
+                Consequence::Tag(tag) => write!(f, " tag={}", tag)?,
+                Consequence::Monitor(monitor) => write!(f, " monitor={}", monitor)?,
+                Consequence::Floating(on) => write!(f, " floating={}", on_off(*on))?,
+                Consequence::Fullscreen(on) => write!(f, " fullscreen={}", on_off(*on))?,
+                Consequence::Focus(on) => write!(f, " focus={}", on_off(*on))?,
+                Consequence::Geometry(geometry) => {
+                    let (x, y, w, h) = geometry.values();
+                    write!(f, " geometry={}x{}{:+}{:+}", w, h, x, y)?
+                }
+                Consequence::Manage(on) => write!(f, " manage={}", on_off(*on))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split rule arguments on whitespace. Single or double quotes keep text
+/// together and are removed. A word without = or ~ outside of quotes is
+/// joined to the previous one, with a space.
+fn split_args(args: &str) -> Result<Vec<String>> {
+    let mut words: Vec<String> = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut has_separator = false;
+    let mut quote = None;
+    let mut end_word = |word: &mut String, has_separator: bool| {
+        match words.last_mut() {
+            Some(last) if !has_separator => {
+                last.push(' ');
+                last.push_str(word);
+            }
+            _ => words.push(word.clone()),
+        }
+        word.clear();
+    };
+    for c in args.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    end_word(&mut word, has_separator);
+                }
+                in_word = false;
+                has_separator = false;
+            }
+            (None, c) => {
+                has_separator |= c == '=' || c == '~';
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(anyhow!("rule: unterminated quote in {}", args));
+    }
+    if in_word {
+        end_word(&mut word, has_separator);
+    }
+    Ok(words)
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on { "on" } else { "off" }
+}
+
+/// Parse an X geometry: WxH+X+Y, where the offsets may be negative
+fn parse_geometry(value: &str) -> Result<Rectangle> {
+    let invalid = || anyhow!("rule: invalid geometry {}, expected WxH+X+Y", value);
+    let offsets = value.find(['+', '-']).ok_or_else(invalid)?;
+    let (size, offsets) = value.split_at(offsets);
+    let (w, h) = size.split_at(size.find('x').ok_or_else(invalid)?);
+    let y = offsets[1..].find(['+', '-']).ok_or_else(invalid)? + 1;
+    let (x, y) = offsets.split_at(y);
+    Ok(Rectangle::new(
+        x.parse().map_err(|_| invalid())?,
+        y.parse().map_err(|_| invalid())?,
+        w.parse().map_err(|_| invalid())?,
+        h[1..].parse().map_err(|_| invalid())?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn firefox() -> WindowProperties {
+        WindowProperties {
+            class: "Firefox".to_string(),
+            instance: "Navigator".to_string(),
+            title: "Rust - YouTube - Mozilla Firefox".to_string(),
+            window_types: vec!["NORMAL".to_string()],
+            ..WindowProperties::default()
+        }
+    }
+
+    fn parse(args: &str) -> Result<Rule> {
+        Rule::parse(1, args)
+    }
+
+    fn outcome(rule: &Rule) -> Outcome {
+        let mut outcome = Outcome::default();
+        rule.apply(&mut outcome);
+        outcome
+    }
+
+    #[test]
+    fn exact_conditions_match_the_whole_value() {
+        let rule = parse("class=Firefox tag=3").unwrap();
+        assert!(rule.matches(&firefox()));
+        let rule = parse("class=Fire tag=3").unwrap();
+        assert!(!rule.matches(&firefox()));
+        let rule = parse("transient_for=on tag=3").unwrap();
+        assert!(!rule.matches(&firefox()));
+    }
+
+    #[test]
+    fn regex_conditions_match_the_whole_value() {
+        let rule = parse("title~.*YouTube.* floating=on").unwrap();
+        assert!(rule.matches(&firefox()));
+        let rule = parse("title~YouTube floating=on").unwrap();
+        assert!(!rule.matches(&firefox()));
+        let rule = parse("windowtype~'DIALOG|NORMAL' floating=on").unwrap();
+        assert!(rule.matches(&firefox()));
+        assert!(parse("title~( floating=on").is_err());
+    }
+
+    #[test]
+    fn window_types_match_with_or_without_prefix() {
+        let mut dialog = firefox();
+        dialog.window_types = vec!["DIALOG".to_string()];
+        let rule = parse("windowtype=_NET_WM_WINDOW_TYPE_DIALOG focus=on").unwrap();
+        assert!(rule.matches(&dialog));
+        assert!(!rule.matches(&firefox()));
+        let rule = parse("windowtype~'_NET_WM_WINDOW_TYPE_(DIALOG|UTILITY|SPLASH)' floating=on").unwrap();
+        assert!(rule.matches(&dialog));
+        let rule = parse("windowtype=DIALOG focus=on").unwrap();
+        assert!(rule.matches(&dialog));
+        let rule = parse("windowtype~WINDOW_TYPE_DIALOG focus=on").unwrap();
+        assert!(!rule.matches(&dialog));
+    }
+
+    #[test]
+    fn all_conditions_must_match() {
+        let rule = parse("class=Firefox instance=Navigator tag=3").unwrap();
+        assert!(rule.matches(&firefox()));
+        let rule = parse("class=Firefox instance=Toolkit tag=3").unwrap();
+        assert!(!rule.matches(&firefox()));
+        let rule = parse("tag=3").unwrap();
+        assert!(rule.matches(&WindowProperties::default()));
+    }
+
+    #[test]
+    fn values_can_hold_spaces() {
+        let rule = parse("title='Rust - YouTube - Mozilla Firefox' tag=3").unwrap();
+        assert!(rule.matches(&firefox()));
+        let rule = parse("title=\"Rust - YouTube - Mozilla Firefox\" tag=3").unwrap();
+        assert!(rule.matches(&firefox()));
+        // The quotes were removed by the shell
+        let rule = parse("title~.* Mozilla Firefox tag=web browser").unwrap();
+        assert!(rule.matches(&firefox()));
+        assert_eq!(outcome(&rule).tag.as_deref(), Some("web browser"));
+        assert!(parse("title='Rust tag=3").is_err());
+    }
+
+    #[test]
+    fn consequences_fill_the_outcome() {
+        let rule = parse("tag=3 monitor=1 floating=on fullscreen=off focus=on manage=off").unwrap();
+        let outcome = outcome(&rule);
+        assert_eq!(outcome.tag.as_deref(), Some("3"));
+        assert_eq!(outcome.monitor, Some(1));
+        assert_eq!(outcome.floating, Some(true));
+        assert_eq!(outcome.fullscreen, Some(false));
+        assert_eq!(outcome.focus, Some(true));
+        assert_eq!(outcome.manage, Some(false));
+        assert_eq!(outcome.geometry, None);
+    }
+
+    #[test]
+    fn later_rules_override_earlier_ones() {
+        let mut outcome = Outcome::default();
+        parse("tag=3 floating=on").unwrap().apply(&mut outcome);
+        parse("tag=4").unwrap().apply(&mut outcome);
+        assert_eq!(outcome.tag.as_deref(), Some("4"));
+        assert_eq!(outcome.floating, Some(true));
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        assert!(parse("class=Firefox").is_err());
+        assert!(parse("").is_err());
+        assert!(parse("color=red tag=3").is_err());
+        assert!(parse("color~red tag=3").is_err());
+        assert!(parse("floating=maybe").is_err());
+        assert!(parse("monitor=first").is_err());
+    }
+
+    #[test]
+    fn geometry_accepts_negative_offsets() {
+        assert_eq!(parse_geometry("800x600+10+20").unwrap().values(), (10, 20, 800, 600));
+        assert_eq!(parse_geometry("800x600-10+20").unwrap().values(), (-10, 20, 800, 600));
+        assert_eq!(parse_geometry("800x600+10-20").unwrap().values(), (10, -20, 800, 600));
+        assert!(parse_geometry("800x600").is_err());
+        assert!(parse_geometry("800+10+20").is_err());
+        assert!(parse_geometry("800x600+10").is_err());
+        assert!(parse_geometry("axb+1+2").is_err());
+    }
+
+    #[test]
+    fn display_gives_back_the_rule() {
+        let args = "class=Firefox title~'.* - Mozilla Firefox' instance='Web Browser' tag='web browser' geometry=800x600-10+20 floating=on";
+        let rule = parse(args).unwrap();
+        assert_eq!(rule.to_string(), format!("1: {}", args));
+        let reparsed = parse(&rule.to_string()[3..]).unwrap();
+        assert_eq!(reparsed.to_string(), rule.to_string());
+    }
+}
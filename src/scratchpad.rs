@@ -0,0 +1,77 @@
+use xcb::Window;
+
+/**
+ * A named window that can be shown floating over the current tag and
+ * hidden again.
+ *
+ * It is either registered from an existing window, or from a command and
+ * the WM_CLASS of the window it opens, in which case the window is looked
+ * up or started the first time the scratchpad is shown.
+ */
+pub struct Scratchpad {
+    name: String,
+    class: Option<String>,
+    command: Option<String>,
+    window: Option<Window>,
+    /// The command was started and its window hasn't been mapped yet
+    spawned: bool,
+}
+
+impl Scratchpad {
+    /// A scratchpad holding an existing window
+    pub fn from_window(name: &str, window: Window) -> Scratchpad {
+        Scratchpad {
+            name: name.to_string(),
+            class: None,
+            command: None,
+            window: Some(window),
+            spawned: false,
+        }
+    }
+
+    /// A scratchpad holding the window of a command, recognized by its class
+    pub fn from_command(name: &str, class: &str, command: &str) -> Scratchpad {
+        Scratchpad {
+            name: name.to_string(),
+            class: Some(class.to_string()),
+            command: Some(command.to_string()),
+            window: None,
+            spawned: false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn class(&self) -> Option<&str> {
+        self.class.as_deref()
+    }
+
+    pub fn command(&self) -> Option<&str> {
+        self.command.as_deref()
+    }
+
+    pub fn window(&self) -> Option<Window> {
+        self.window
+    }
+
+    pub fn set_window(&mut self, window: Option<Window>) {
+        self.window = window;
+        self.spawned = false;
+    }
+
+    pub fn is_spawned(&self) -> bool {
+        self.spawned
+    }
+
+    pub fn set_spawned(&mut self) {
+        self.spawned = true;
+    }
+
+    /// Whether a newly mapped window of the given class is the one this
+    /// scratchpad is waiting for
+    pub fn captures(&self, class: &str) -> bool {
+        self.spawned && self.window.is_none() && self.class() == Some(class)
+    }
+}
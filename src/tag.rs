@@ -1,27 +1,203 @@
 use xcb::Window;
 
+use crate::{
+    layouts::{Layout, LayoutParams, Transform, swap_id},
+    xconnection::Rectangle,
+};
+
+/// Gaps in pixels, see Config::gap_px and Config::outer_gap_px
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Gaps {
+    pub inner: u32,
+    pub outer: u32,
+}
+
+/// A set of windows along with the layout state used to tile them
 pub struct Tag {
     name: String,
+    /// Stack of windows, in layout order: the first one is the master
     windows: Vec<Window>,
+    /// Index of the window of this tag that has, or last had, the focus
+    focused: Option<usize>,
+    /// Windows of this tag from the least to the most recently focused
+    history: Vec<Window>,
+    layout: Box<dyn Layout>,
+    layout_params: LayoutParams,
+    /// Gaps to use instead of the global ones
+    gaps: Option<Gaps>,
+    /// Mirroring / rotation applied to whichever layout is in use
+    transform: Transform,
 }
 
 impl Tag {
-    pub fn new(name: impl Into<String>) -> Tag {
+    pub fn new(name: impl Into<String>, layout: Box<dyn Layout>, layout_params: LayoutParams) -> Tag {
         Tag {
             name: name.into(),
             windows: Vec::new(),
+            focused: None,
+            history: Vec::new(),
+            layout,
+            layout_params,
+            gaps: None,
+            transform: Transform::default(),
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn add_window(&mut self, win: Window) {
         self.windows.push(win);
     }
 
+    /// Remove a window, keeping the order of the others. If it was the focused
+    /// one, the focus goes back to the window of this tag focused before it,
+    /// or to the window that takes its place in the stack.
     pub fn remove_window(&mut self, win: Window) {
-        self.windows.swap_remove(self.windows.iter().position(|x| *x == win).expect("window not found in tag"));
+        let index = self.windows.iter().position(|x| *x == win).expect("window not found in tag");
+        self.windows.remove(index);
+        self.history.retain(|&w| w != win);
+        self.focused = match self.focused {
+            _ if self.windows.is_empty() => None,
+            Some(f) if f > index => Some(f - 1),
+            Some(f) if f == index => self
+                .history
+                .last()
+                .and_then(|&last| self.windows.iter().position(|&w| w == last))
+                .or(Some(index.min(self.windows.len() - 1))),
+            focused => focused,
+        };
+    }
+
+    /// The window of this tag that has, or last had, the focus
+    pub fn focused_window(&self) -> Option<Window> {
+        self.focused.map(|i| self.windows[i])
+    }
+
+    /// Remember a window as the focused one of this tag
+    pub fn focus_window(&mut self, win: Window) {
+        if let Some(index) = self.windows.iter().position(|&w| w == win) {
+            self.focused = Some(index);
+            self.history.retain(|&w| w != win);
+            self.history.push(win);
+        }
+    }
+
+    /// Exchange the places of two windows in this tag, both in the window
+    /// order and in the layout state. If only one of them is in this tag, the
+    /// other one takes its place.
+    pub fn swap_windows(&mut self, a: Window, b: Window) {
+        let focused = self.focused_window();
+        for win in self.windows.iter_mut() {
+            swap_id(win, a, b);
+        }
+        self.layout.swap_windows(a, b);
+        // The focus stays with the window, wherever it went
+        if let Some(f) = focused {
+            self.focus_window(f);
+        }
+    }
+
+    /// Move a window to the place of `master` in the stack, shifting the
+    /// windows in between down by one. Layouts that keep their own state see
+    /// this as the two windows swapping places.
+    pub fn promote(&mut self, win: Window, master: Window) {
+        let from = self.windows.iter().position(|&w| w == win);
+        let to = self.windows.iter().position(|&w| w == master);
+        if let (Some(from), Some(to)) = (from, to) {
+            let focused = self.focused_window();
+            let win = self.windows.remove(from);
+            self.windows.insert(to, win);
+            self.layout.swap_windows(win, master);
+            if let Some(f) = focused {
+                self.focus_window(f);
+            }
+        }
     }
 
     pub fn windows(&self) -> &Vec<Window> {
         &self.windows
     }
+
+    pub fn layout(&self) -> &dyn Layout {
+        self.layout.as_ref()
+    }
+
+    pub fn layout_mut(&mut self) -> &mut dyn Layout {
+        self.layout.as_mut()
+    }
+
+    pub fn set_layout(&mut self, layout: Box<dyn Layout>) {
+        self.layout = layout;
+    }
+
+    pub fn layout_params_mut(&mut self) -> &mut LayoutParams {
+        &mut self.layout_params
+    }
+
+    pub fn gaps(&self) -> Option<Gaps> {
+        self.gaps
+    }
+
+    pub fn set_gaps(&mut self, gaps: Option<Gaps>) {
+        self.gaps = gaps;
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    pub fn transform_mut(&mut self) -> &mut Transform {
+        &mut self.transform
+    }
+
+    /// Run the layout of this tag on the given windows, which are the tiled
+    /// subset of the windows of the tag
+    pub fn arrange(&mut self, frame: &Rectangle, windows: &[Window], focused: Option<Window>) -> Vec<Rectangle> {
+        self.transform.arrange(self.layout.as_mut(), frame, windows, &self.layout_params, focused)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layouts::Tall;
+
+    fn tag(windows: &[Window]) -> Tag {
+        let mut tag = Tag::new("test", Box::new(Tall), LayoutParams { nmaster: 1, main_ratio: 0.5 });
+        for &win in windows {
+            tag.add_window(win);
+        }
+        tag
+    }
+
+    #[test]
+    fn removing_the_focused_window_focuses_the_last_one() {
+        let mut tag = tag(&[1, 2, 3, 4]);
+        tag.focus_window(2);
+        tag.focus_window(4);
+        tag.focus_window(1);
+        tag.remove_window(1);
+        assert_eq!(tag.focused_window(), Some(4));
+        tag.remove_window(4);
+        assert_eq!(tag.focused_window(), Some(2));
+        // 3 was never focused, it takes the place of 2 in the stack
+        tag.remove_window(2);
+        assert_eq!(tag.focused_window(), Some(3));
+        tag.remove_window(3);
+        assert_eq!(tag.focused_window(), None);
+    }
+
+    #[test]
+    fn removing_another_window_keeps_the_focus() {
+        let mut tag = tag(&[1, 2, 3]);
+        tag.focus_window(1);
+        tag.focus_window(3);
+        tag.remove_window(1);
+        assert_eq!(tag.focused_window(), Some(3));
+        tag.remove_window(2);
+        assert_eq!(tag.focused_window(), Some(3));
+        assert_eq!(tag.windows(), &vec![3]);
+    }
 }
@@ -38,9 +38,36 @@ impl View {
         self.active_tag
     }
     
+    /// Show a tag on the virtual monitor of the active tag and make it active.
+    /// If the tag is already shown on another monitor it only becomes active.
+    /// Returns the tag that was hidden to make room, if any.
+    pub fn show_tag(&mut self, tag: TagId) -> Option<TagId> {
+        if self.tags.contains(&tag) {
+            self.active_tag = tag;
+            return None;
+        }
+        let i = self.tags.iter().position(|&t| t == self.active_tag)?;
+        let hidden = std::mem::replace(&mut self.tags[i], tag);
+        self.active_tag = tag;
+        Some(hidden)
+    }
+
+    /// The tags shown on the virtual monitors, in monitor order
+    pub fn tags(&self) -> &[TagId] {
+        &self.tags
+    }
+
     pub fn has_tag(&self, tag: TagId) -> bool {
         self.tags.contains(&tag)
     }
+
+    /// The region of the virtual monitor showing the tag, if the tag is visible
+    pub fn region_of(&self, tag: TagId) -> Option<Rectangle> {
+        self.tags
+            .iter()
+            .position(|&t| t == tag)
+            .map(|i| self.vmons[i].region())
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -54,4 +81,8 @@ impl VirtualMonitor {
             region,
         }
     }
+
+    pub fn region(&self) -> Rectangle {
+        self.region
+    }
 }
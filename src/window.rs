@@ -1,5 +1,6 @@
 use xcb::Window;
 use crate::wm::TagId;
+use crate::xconnection::{Rectangle, SizeHints};
 
 /**
  * Meta-data around a window that we are handling.
@@ -20,8 +21,12 @@ pub struct WindowInfo {
     // pub(crate) mapped: bool,
     // pub(crate) wm_managed: bool,
     fullscreen: bool,
+    sticky: bool,
     mapped: bool,
     // wm_managed: bool,
+    size_hints: SizeHints,
+    /// Where the window was the last time it was floating
+    floating_geometry: Option<Rectangle>,
 }
 
 impl WindowInfo {
@@ -42,8 +47,11 @@ impl WindowInfo {
             // workspace,
             floating,
             fullscreen: false,
+            sticky: false,
             mapped: false,
             // wm_managed: true,
+            size_hints: SizeHints::default(),
+            floating_geometry: None,
         }
     }
 
@@ -52,6 +60,11 @@ impl WindowInfo {
         self.id
     }
 
+    /// The WM_NAME property of this window, as read when it was mapped
+    pub fn wm_name(&self) -> &str {
+        &self.wm_name
+    }
+
     /// The WM_CLASS property of this window
     pub fn wm_class(&self) -> &str {
         &self.wm_class
@@ -60,5 +73,53 @@ impl WindowInfo {
     pub fn tag(&self) -> TagId {
         self.tag
     }
+
+    pub fn set_tag(&mut self, tag: TagId) {
+        self.tag = tag;
+    }
+
+    /// Floating windows are not tiled and stay above the tiled ones
+    pub fn is_floating(&self) -> bool {
+        self.floating
+    }
+
+    pub fn set_floating(&mut self, floating: bool) {
+        self.floating = floating;
+    }
+
+    /// Fullscreen windows cover their monitor, without a border
+    pub fn is_fullscreen(&self) -> bool {
+        self.fullscreen
+    }
+
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        self.fullscreen = fullscreen;
+    }
+
+    /// Sticky windows float and stay on their monitor when it shows another tag
+    pub fn is_sticky(&self) -> bool {
+        self.sticky
+    }
+
+    pub fn set_sticky(&mut self, sticky: bool) {
+        self.sticky = sticky;
+    }
+
+    pub fn floating_geometry(&self) -> Option<Rectangle> {
+        self.floating_geometry
+    }
+
+    pub fn set_floating_geometry(&mut self, geometry: Option<Rectangle>) {
+        self.floating_geometry = geometry;
+    }
+
+    /// The WM_NORMAL_HINTS of this window
+    pub fn size_hints(&self) -> &SizeHints {
+        &self.size_hints
+    }
+
+    pub fn set_size_hints(&mut self, size_hints: SizeHints) {
+        self.size_hints = size_hints;
+    }
 }
 
@@ -1,19 +1,23 @@
 use crate::{
-    xconnection::{XcbConnection, XEvent, XcbKey, Rectangle},
-    bindings::Bindings,
+    xconnection::{XcbConnection, XEvent, XcbKey, Rectangle, NetWmState, Point, Border},
+    bindings::{Bindings, parse_modifiers, spawn},
     window::WindowInfo,
+    scratchpad::Scratchpad,
+    rules::{Rule, Outcome, WindowProperties},
     ipc,
     ipc::IpcServer,
     workspace::{Workspace},
     // view::View,
-    tag::{Tag},
+    tag::{Tag, Gaps},
     view::{View, VirtualMonitor},
+    layouts::{LayoutRegistry, LayoutParams, Direction, find_in_direction, MIN_MAIN_RATIO, MAX_MAIN_RATIO},
 };
 
 use std::{
     // io::Read,
     // process::Command,
     collections::HashMap,
+    fs,
 };
 use anyhow::{Result, Context, anyhow};
 
@@ -46,22 +50,62 @@ use xcb::{Window, Atom};
 
 // atoms!(WM_DELETE_WINDOW);
 
+// _NET_WM_STATE client message actions
+const NET_WM_STATE_REMOVE: u32 = 0;
+const NET_WM_STATE_ADD: u32 = 1;
+const NET_WM_STATE_TOGGLE: u32 = 2;
+// WM_CHANGE_STATE client message state (ICCCM 4.1.4)
+const WM_CHANGE_STATE_ICONIC: u32 = 3;
+
 pub type WsId = usize;
 pub type TagId = usize;
 
+/// What a mouse drag does to the window it started on
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum DragKind {
+    Move,
+    Resize,
+}
+
+/// A mouse drag of a floating window in progress
+#[derive(Debug, Clone, Copy)]
+struct Drag {
+    win: Window,
+    kind: DragKind,
+    /// Pointer position when the button was pressed
+    start: (i32, i32),
+    /// Window geometry when the button was pressed
+    geometry: Rectangle,
+    /// Window geometry after the last motion event
+    current: Rectangle,
+}
+
 // pub struct WindowManager<T: 'a XConn> {
 //     conn: &'a dyn XConn,
 // }
 pub struct WindowManager<'a> {
     conn: &'a XcbConnection,
     config: Config,
-    default_monitors: Vec<Rectangle>,
     bindings: Bindings<'a>,
     windows: HashMap<Window, WindowInfo>,
     workspaces: Vec<Workspace>,
     active_workspace: WsId,
     tags: Vec<Tag>,
+    layouts: LayoutRegistry,
     focused_window: Option<Window>,
+    /// Windows of all tags from the least to the most recently focused
+    focus_history: Vec<Window>,
+    /// Windows that asked for attention, oldest first
+    urgent_windows: Vec<Window>,
+    /// Iconified windows, in the order they were hidden
+    hidden_windows: Vec<Window>,
+    scratchpads: Vec<Scratchpad>,
+    /// Rules applied to new windows, in the order they were added
+    rules: Vec<Rule>,
+    next_rule_id: usize,
+    /// Terminals swallowed by the window started from them
+    swallowed: HashMap<Window, Window>,
+    drag: Option<Drag>,
     // atoms: InternedAtoms,
     ipc_server: IpcServer<'a>,
     running: bool,
@@ -83,26 +127,45 @@ impl<'a> WindowManager<'a> {
             .iter()
             .map(|name| Workspace::new(name, View::default(virtual_monitors.clone())))
             .collect();
+        let layouts = LayoutRegistry::default();
         let tags: Vec<Tag> = config
             .tags
             .iter()
-            .map(|name| Tag::new(name))
-            .collect();
+            .map(|name| {
+                Ok(Tag::new(
+                    name,
+                    layouts.build(0).context("No layout registered")?,
+                    LayoutParams {
+                        nmaster: config.nmaster,
+                        main_ratio: config.main_ratio,
+                    },
+                ))
+            })
+            .collect::<Result<_>>()?;
         let mut wm = WindowManager {
             conn,
             config,
-            default_monitors: monitors,
             bindings: Bindings::new(conn),
             windows: HashMap::new(),
             workspaces,
             active_workspace: 0,
             tags,
+            layouts,
             focused_window: None,
+            focus_history: Vec::new(),
+            urgent_windows: Vec::new(),
+            hidden_windows: Vec::new(),
+            scratchpads: Vec::new(),
+            rules: Vec::new(),
+            next_rule_id: 0,
+            swallowed: HashMap::new(),
+            drag: None,
             // atoms,
             ipc_server,
             running: false,
         };
 
+        wm.grab_buttons();
         wm.conn.flush();
 
         Ok(wm)
@@ -114,11 +177,16 @@ impl<'a> WindowManager<'a> {
             if let Some(event) = self.conn.wait_for_event() {
                 debug!("got XEvent: {:?}", event);
                 match event {
-                    // XEvent::ClientCommand { format, window, atom, data} => self.handle_client_message(format, window, atom, data),
+                    XEvent::ClientCommand { format, window, atom, data } => {
+                        self.handle_client_message(format, window, atom, data)
+                    }
                     // XEvent::CreateNotify { id } => self.handle_create_notify(id),
                     XEvent::KeyPress { code } => self.handle_key_press(code),
+                    XEvent::ButtonPress { id, button, rpt } => self.handle_button_press(id, button, rpt),
+                    XEvent::ButtonRelease { button } => self.handle_button_release(button),
+                    XEvent::MotionNotify { rpt } => self.handle_motion_notify(rpt),
                     XEvent::MapRequest { id, ignore } => self.handle_map_request(id, ignore),
-                    // XEvent::Enter { id, rpt, wpt } => self.handle_enter_notify(id, rpt, wpt),
+                    XEvent::Enter { id, .. } => self.handle_enter_notify(id),
                     // XEvent::Leave { id, rpt, wpt } => self.handle_leave_notify(id, rpt, wpt),
                     XEvent::DestroyNotify { id } => self.handle_destroy_notify(id),
                     // XEvent::ScreenChange => self.handle_screen_change(),
@@ -135,7 +203,6 @@ impl<'a> WindowManager<'a> {
                     // XEvent::ClientMessage { id, dtype, data } => {
                     //     self.handle_client_message(id, &dtype, &data)
                     // }
-                    _ => (),
                 }
                 // run_hooks!(event_handled, self,);
                 self.conn.flush();
@@ -263,6 +330,33 @@ impl<'a> WindowManager<'a> {
      * received from the X event loop (i.e. to avoid emitting and picking up the event
      * ourselves)
      */
+    fn handle_client_message(&mut self, format: u8, win: Window, atom: Atom, data: [u8; 20]) {
+        if format != 32 || !self.windows.contains_key(&win) {
+            return;
+        }
+        let data: Vec<u32> = data
+            .chunks(4)
+            .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        if atom == self.conn.wm_change_state_atom() && data[0] == WM_CHANGE_STATE_ICONIC {
+            self.hide_window(win);
+        }
+        if atom == self.conn.net_wm_state_atom() {
+            // data: action, first property, optional second property, source
+            for &prop in &data[1..3] {
+                if let Some(state) = self.conn.net_wm_state_from_atom(prop) {
+                    let on = match data[0] {
+                        NET_WM_STATE_REMOVE => false,
+                        NET_WM_STATE_ADD => true,
+                        NET_WM_STATE_TOGGLE => !self.has_state(win, state),
+                        _ => continue,
+                    };
+                    self.set_state(win, state, on);
+                }
+            }
+        }
+    }
+
     fn handle_key_press(&mut self, key: XcbKey) {
         if let Some(action) = self.bindings.get_action(&key) {
         // if let Some(action) = self.key_bindings.get(&key).cloned() {
@@ -280,10 +374,91 @@ impl<'a> WindowManager<'a> {
         }
     }
 
+    /// Start moving or resizing a window with the mouse.
+    /// Tiled windows are floated in place first.
+    fn handle_button_press(&mut self, win: Window, button: u8, rpt: Point) {
+        let kind = if button == self.config.move_button {
+            DragKind::Move
+        } else if button == self.config.resize_button {
+            DragKind::Resize
+        } else {
+            return;
+        };
+        if !self.windows.contains_key(&win) || self.is_fullscreen(win) {
+            return;
+        }
+        if !self.is_floating(win) {
+            let geometry = self.conn.get_geometry(win).ok();
+            if let Some(info) = self.windows.get_mut(&win) {
+                info.set_floating_geometry(geometry);
+            }
+            self.set_floating(win, true);
+        }
+        self.change_focus(Some(win));
+        if let Ok(geometry) = self.conn.get_geometry(win) {
+            self.drag = Some(Drag {
+                win,
+                kind,
+                start: (rpt.x as i32, rpt.y as i32),
+                geometry,
+                current: geometry,
+            });
+        }
+    }
+
+    fn handle_motion_notify(&mut self, rpt: Point) {
+        let drag = match self.drag.as_mut() {
+            Some(drag) => drag,
+            None => return,
+        };
+        let (dx, dy) = (rpt.x as i32 - drag.start.0, rpt.y as i32 - drag.start.1);
+        let (x, y, w, h) = drag.geometry.values();
+        drag.current = match drag.kind {
+            DragKind::Move => Rectangle::new(x + dx, y + dy, w, h),
+            DragKind::Resize => {
+                let (_, _, w, h) = drag.geometry.resize(dx, dy).values();
+                let (w, h) = match self.windows.get(&drag.win) {
+                    Some(info) if self.config.size_hints => info.size_hints().constrain(w, h),
+                    _ => (w, h),
+                };
+                Rectangle::new(x, y, w, h)
+            }
+        };
+        self.conn.configure_window(drag.win, Some(drag.current), None, None);
+    }
+
+    fn handle_button_release(&mut self, button: u8) {
+        if button != self.config.move_button && button != self.config.resize_button {
+            return;
+        }
+        if let Some(drag) = self.drag.take() {
+            if let Some(info) = self.windows.get_mut(&drag.win) {
+                info.set_floating_geometry(Some(drag.current));
+            }
+        }
+    }
+
+    fn handle_enter_notify(&mut self, win: Window) {
+        if self.config.focus_follows_mouse && self.drag.is_none() && self.windows.contains_key(&win) {
+            self.change_focus(Some(win));
+        }
+    }
+
     fn handle_property_notify(&mut self, win: Window, atom: Atom, is_root: bool) {
         if let Some(command) = self.ipc_server.get_command(win, atom) {
             self.handle_command(command, win);
         }
+        if atom == xcb::ATOM_WM_NORMAL_HINTS {
+            if let Some(info) = self.windows.get_mut(&win) {
+                info.set_size_hints(self.conn.get_size_hints(win).unwrap_or_default());
+                let tag = info.tag();
+                self.apply_layout(tag);
+            }
+        }
+        if atom == xcb::ATOM_WM_HINTS && self.windows.contains_key(&win) {
+            let urgent = self.conn.get_urgency_hint(win);
+            self.set_urgent(win, urgent);
+        }
         // if atom ==  || atom == "_NET_WM_NAME" {
         //     if let Ok(name) = self.conn.str_prop(id, atom) {
         //         self.client_map.get_mut(&id).map(|c| c.set_name(&name));
@@ -293,13 +468,26 @@ impl<'a> WindowManager<'a> {
     }
 
     fn handle_destroy_notify(&mut self, win: Window) {
+        if self.drag.is_some_and(|drag| drag.win == win) {
+            self.drag = None;
+        }
         if let Some(win_info) = self.windows.get(&win) {
             let tag = win_info.tag();
-            self.tags[tag].remove_window(win);
-            if self.active_workspace().active_view().has_tag(tag) {
-                self.apply_layout(&self.default_monitors[0], &self.tags[tag], 0);
+            let terminal = self.swallowed.remove(&win);
+            // Hidden and swallowed windows already left the layout of their tag
+            let in_layout = !self.is_hidden(win) && !self.is_swallowed(win);
+            if let Some(terminal) = terminal {
+                self.release_swallowed(terminal, win, in_layout);
             }
+            if in_layout {
+                self.tags[tag].remove_window(win);
+                self.apply_layout(tag);
+            }
+            let focused = self.focused_window() == Some(win);
             self.remove_window_info(win);
+            if focused && terminal.is_some() {
+                self.change_focus(terminal);
+            }
         }
     }
 
@@ -308,6 +496,13 @@ impl<'a> WindowManager<'a> {
         if override_redirect {
             return;
         }
+        if self.windows.contains_key(&win) {
+            // Mapping an iconified window is how a client asks to restore it
+            if self.is_hidden(win) {
+                self.focus_anywhere(win);
+            }
+            return;
+        }
 
         // let mut client = Client::new(id, wm_name, wm_class, wix, floating);
         // run_hooks!(new_client, self, &mut client);
@@ -329,19 +524,101 @@ impl<'a> WindowManager<'a> {
     fn add_window(&mut self, win: Window) {
         let wm_name = self.conn.get_wm_name(win).unwrap_or(String::new());
         let wm_class = self.conn.get_wm_class(win).unwrap_or(String::new());
-        let active_tag = self.active_workspace().active_tag();
-        let window_info = WindowInfo::new(win, wm_name, wm_class, active_tag, false);
+        let window_types = self.conn.get_window_types(win).unwrap_or_default();
+        let properties = WindowProperties {
+            class: wm_class.clone(),
+            instance: self.conn.get_wm_instance(win).unwrap_or_default(),
+            title: wm_name.clone(),
+            window_types,
+            window_role: self.conn.get_window_role(win).unwrap_or_default(),
+            transient: self.conn.get_transient_for(win).is_some(),
+        };
+        let mut outcome = Outcome::default();
+        for rule in self.rules.iter().filter(|rule| rule.matches(&properties)) {
+            rule.apply(&mut outcome);
+        }
+        if outcome.manage == Some(false) {
+            self.conn.map_window(win);
+            return;
+        }
+
+        let view = self.active_workspace().active_view();
+        let rule_tag = match (&outcome.tag, outcome.monitor) {
+            (Some(name), _) => {
+                let tag = self.tags.iter().position(|t| t.name() == name);
+                if tag.is_none() {
+                    warn!("rule: unknown tag {}", name);
+                }
+                tag
+            }
+            (None, Some(monitor)) => view.tags().get(monitor).copied(),
+            (None, None) => None,
+        };
+        let tag = rule_tag.unwrap_or_else(|| view.active_tag());
+        let floating = properties
+            .window_types
+            .iter()
+            .any(|t| self.config.floating_window_types.contains(&t.as_str()));
+        let floating = outcome.floating.unwrap_or(floating);
+        // The window a scratchpad command was started for shows up floating
+        let scratchpad = self.scratchpads.iter_mut().find(|s| s.captures(&wm_class));
+        let floating = floating || scratchpad.is_some();
+        if let Some(scratchpad) = scratchpad {
+            scratchpad.set_window(Some(win));
+        }
+        let terminal = if floating { None } else { self.swallow_target(win, &properties, tag) };
+        let mut window_info = WindowInfo::new(win, wm_name, wm_class, tag, floating);
+        window_info.set_size_hints(self.conn.get_size_hints(win).unwrap_or_default());
+        window_info.set_floating_geometry(outcome.geometry);
         self.windows.insert(win, window_info);
-        // let active_tag = self.active_workspace().active_view().active_tag();
-        self.tags[active_tag].add_window(win);
-        self.change_focus(Some(win));
+        self.tags[tag].add_window(win);
+        if let Some(terminal) = terminal {
+            self.swallow(terminal, win);
+        }
 
         self.conn.mark_new_window(win);
         self.conn.configure_window(win, None, Some(self.config.border_width_px), Some(true));
+        if floating {
+            match outcome.geometry {
+                Some(geometry) => self.conn.configure_window(win, Some(geometry), None, None),
+                None => self.center_floating(win, tag),
+            }
+        }
+        let states = self.conn.get_net_wm_state(win);
+        if !floating && outcome.geometry.is_none() && states.contains(&NetWmState::Sticky) {
+            // Sticky windows float at the size they asked for, like other
+            // floating windows, instead of the default floating size
+            self.center_floating(win, tag);
+            let geometry = self.conn.get_geometry(win).ok();
+            if let Some(info) = self.windows.get_mut(&win) {
+                info.set_floating_geometry(geometry);
+            }
+        }
+        for state in states {
+            self.set_state(win, state, true);
+        }
+        if let Some(fullscreen) = outcome.fullscreen {
+            self.set_fullscreen(win, fullscreen);
+        }
+        if self.conn.get_urgency_hint(win) {
+            self.set_urgent(win, true);
+        }
 
         // self.draw_view(self.active_workspace().active_view());
-        self.apply_layout(&self.default_monitors[0], &self.tags[active_tag], 0);
-        self.conn.map_window(win);
+        // Tile before moving the focus so that layouts can place the new window
+        // next to the one that was focused (e.g. bsp splits the focused node)
+        self.apply_layout(tag);
+        // Windows sent to a hidden tag are mapped when the tag is shown
+        let visible = self.active_workspace().active_view().has_tag(tag);
+        match outcome.focus {
+            Some(true) if !visible => self.focus_anywhere(win),
+            None | Some(true) if visible => self.change_focus(Some(win)),
+            _ => (),
+        }
+        if visible {
+            self.conn.map_window(win);
+        }
+        self.ignore_crossing_events();
     }
     // fn map_window_if_needed(&mut self, id: Window) {
     //     if let Some(c) = self.client_map.get_mut(&id) {
@@ -359,21 +636,134 @@ impl<'a> WindowManager<'a> {
     fn draw_view(&self, view: &View) {
     }
 
-    fn apply_layout(&self, frame: &Rectangle, tag: &Tag, layout: u8) {
-        let num_win = tag.windows().len();
-        debug!("num_win {}", num_win);
-        for (i, &win) in tag.windows().iter().enumerate() {
-            let (x, y, w, h) = frame.values();
-            let reg = Rectangle::new(
-                x + (w / num_win as u32 * i as u32) as i32,
-                y,
-                w / (num_win as u32),
-                h);
+    /// With focus follows mouse, make sure that the windows we just moved,
+    /// mapped or raised under the pointer don't steal the focus
+    fn ignore_crossing_events(&self) {
+        if self.config.focus_follows_mouse {
+            self.conn.ignore_crossing_events();
+        }
+    }
+
+    /// Grab the configured mouse buttons, replacing any previous grab
+    fn grab_buttons(&self) {
+        self.conn.ungrab_buttons();
+        for &button in &[self.config.move_button, self.config.resize_button] {
+            self.conn.grab_button(button, self.config.mouse_modifier);
+        }
+    }
+
+    /// Tile the windows of all tags shown in the active view
+    fn apply_visible_layouts(&mut self) {
+        for tag in self.active_workspace().active_view().tags().to_vec() {
+            self.apply_layout(tag);
+        }
+    }
+
+    /// The windows of a tag that take part in tiling, in tag order
+    fn tiled_windows(&self, tag: TagId) -> Vec<Window> {
+        self.tags[tag]
+            .windows()
+            .iter()
+            .copied()
+            .filter(|&w| !self.is_floating(w) && !self.is_fullscreen(w))
+            .collect()
+    }
+
+    fn is_floating(&self, win: Window) -> bool {
+        self.windows.get(&win).is_some_and(|info| info.is_floating())
+    }
+
+    fn is_sticky(&self, win: Window) -> bool {
+        self.windows.get(&win).is_some_and(|info| info.is_sticky())
+    }
+
+    fn is_fullscreen(&self, win: Window) -> bool {
+        self.windows.get(&win).is_some_and(|info| info.is_fullscreen())
+    }
+
+    /// Center a floating window at its current size on the window it is
+    /// transient for, or on the monitor of its tag
+    fn center_floating(&self, win: Window, tag: TagId) {
+        let area = self
+            .conn
+            .get_transient_for(win)
+            .filter(|parent| self.windows.contains_key(parent))
+            .and_then(|parent| self.conn.get_geometry(parent).ok())
+            .or_else(|| self.active_workspace().active_view().region_of(tag));
+        if let (Some(area), Ok(geometry)) = (area, self.conn.get_geometry(win)) {
+            let (_, _, w, h) = geometry.values();
+            let border = 2 * self.config.border_width_px;
+            // The border is drawn outside of the window, so center the outer size
+            let (x, y, _, _) = area.center(w + border, h + border).values();
+            self.conn.configure_window(win, Some(Rectangle::new(x, y, w, h)), None, None);
+        }
+    }
+
+    /// Keep the floating windows of a tag above its tiled windows, and its
+    /// fullscreen windows above everything
+    fn restack(&self, tag: TagId) {
+        let focused = self.focused_window();
+        let mut layers: Vec<(u8, Window)> = self.tags[tag]
+            .windows()
+            .iter()
+            .filter_map(|&win| {
+                if self.is_fullscreen(win) {
+                    Some((2, win))
+                } else if self.is_floating(win) {
+                    // The focused floating window goes above the other floating ones
+                    Some(if focused == Some(win) { (1, win) } else { (0, win) })
+                } else {
+                    None
+                }
+            })
+            .collect();
+        layers.sort_by_key(|&(layer, _)| layer);
+        for (_, win) in layers {
+            self.conn.configure_window(win, None, None, Some(true));
+        }
+    }
+
+    /// Tile the windows of a tag if it is shown on a monitor of the active view
+    fn apply_layout(&mut self, tag_id: TagId) {
+        let monitor = match self.active_workspace().active_view().region_of(tag_id) {
+            Some(r) => r,
+            None => return,
+        };
+        let focused = self.focused_window();
+        let tiled = self.tiled_windows(tag_id);
+        let tag = &mut self.tags[tag_id];
+        let (gap, outer_gap) = if self.config.smart_gaps && tiled.len() == 1 {
+            (0, 0)
+        } else {
+            tag.gaps().map_or((self.config.gap_px, self.config.outer_gap_px), |g| (g.inner, g.outer))
+        };
+        // Every window gives up half the inner gap on its top and left sides and
+        // the rest on its bottom and right sides, which leaves a full gap between
+        // neighbours even for odd gaps. The frame is grown by the same amounts to
+        // keep the outer edges at exactly outer_gap.
+        let (gap_before, gap_after) = ((gap / 2) as i32, (gap - gap / 2) as i32);
+        let odd = gap_after - gap_before;
+        let frame = monitor.shrink(outer_gap as i32 - gap_before).resize(odd, odd);
+        debug!("applying layout {} to {} windows", tag.layout().name(), tiled.len());
+        let regions = tag.arrange(&frame, &tiled, focused);
+        let border = self.config.border_width_px as i32;
+        for (&win, &reg) in tiled.iter().zip(regions.iter()) {
+            // X sizes exclude the border, which is drawn outside of the window
+            let mut reg = reg.shrink(gap_before).resize(-odd - 2 * border, -odd - 2 * border);
+            if self.config.size_hints {
+                if let Some(info) = self.windows.get(&win) {
+                    reg = info.size_hints().fit(&reg);
+                }
+            }
             self.conn.configure_window(win, Some(reg), None, None);
-            // self.conn.flush();
-            // self.conn.map_window(win);
         }
-        // self.conn.unmap_window(win);
+        for &win in self.tags[tag_id].windows() {
+            if self.is_fullscreen(win) {
+                self.conn.configure_window(win, Some(monitor), None, None);
+            }
+        }
+        self.restack(tag_id);
+        self.ignore_crossing_events();
     }
 
     /// Kill the focused window.
@@ -385,7 +775,7 @@ impl<'a> WindowManager<'a> {
     }
 
     fn remove_window_info(&mut self, win: Window) {
-        match self.windows.get(&win) {
+        match self.windows.get(&win).cloned() {
             Some(window_info) => {
                 // self.workspaces
                 //     .get_mut(client.workspace())
@@ -393,13 +783,20 @@ impl<'a> WindowManager<'a> {
                 self.windows.remove(&win).map(|c| {
                     debug!("removing window info {} ({})", c.id(), c.wm_class());
                 });
+                self.focus_history.retain(|&w| w != win);
+                self.urgent_windows.retain(|&w| w != win);
+                self.hidden_windows.retain(|&w| w != win);
+                self.swallowed.retain(|_, &mut terminal| terminal != win);
+                for scratchpad in &mut self.scratchpads {
+                    if scratchpad.window() == Some(win) {
+                        scratchpad.set_window(None);
+                    }
+                }
 
                 if self.focused_window() == Some(win) {
-                    if let Some(&win) = self.windows.keys().next() {
-                        self.change_focus(Some(win));
-                    } else {
-                        self.change_focus(None);
-                    }
+                    // The tag already moved its focus to a neighbour in the stack
+                    let next = self.tags[window_info.tag()].focused_window();
+                    self.change_focus(next);
                 }
                 // run_hooks!(remove_client, self, id);
             }
@@ -431,16 +828,32 @@ impl<'a> WindowManager<'a> {
         if win == self.focused_window {
             return
         }
-        if let Some(focused) = self.focused_window {
-            self.conn.set_window_border_color(focused, self.config.unfocused_border_color);
+        let previous = self.focused_window;
+        self.focused_window = win;
+        if let Some(previous) = previous {
+            self.set_border(previous, Border::Unfocused);
         }
         match win {
             Some(w) => {
                 self.conn.focus_window(w);
-                self.conn.set_window_border_color(w, self.config.focused_border_color);
+                // Bring the window to the front, which is how monocle shows it
+                self.conn.configure_window(w, None, None, Some(true));
+                // The user is looking at it now
+                self.set_urgent(w, false);
+                self.set_border(w, Border::Focused);
+                if let Some(info) = self.windows.get(&w) {
+                    let tag = info.tag();
+                    // Put the floating and fullscreen windows back above the
+                    // one just raised, a fullscreen window stays on top
+                    self.restack(tag);
+                    self.tags[tag].focus_window(w);
+                }
+                self.focus_history.retain(|&f| f != w);
+                self.focus_history.push(w);
             }
             None => self.conn.focus_nothing()
         }
+        self.ignore_crossing_events();
 
         // if let Some(wix) = self.workspace_index_for_client(id) {
         //     if let Some(ws) = self.workspaces.get_mut(wix) {
@@ -452,13 +865,13 @@ impl<'a> WindowManager<'a> {
         //     }
         // }
 
-        self.focused_window = win;
         // run_hooks!(focus_change, self, id);
     }
 
     fn handle_command(&mut self, command: String, win: Window) {
         // TODO: better way of parsing strings into different structs
-        let mut command: Vec<&str> = command.split_whitespace().collect();
+        let line = command.trim_end_matches(char::from(0));
+        let mut command: Vec<&str> = line.split_whitespace().collect();
         let cmd = command.remove(0);
         match cmd {
             "bindkey" => {
@@ -471,10 +884,59 @@ impl<'a> WindowManager<'a> {
                 self.ipc_server.send_reply(win, ipc::IPC_STATE_SUCCESS);
                 self.exit();
             }
+            "layout" => {
+                let result = self.layout_command(command);
+                self.send_result(win, result);
+            }
+            "tag" => {
+                let result = match command.first() {
+                    Some(name) => self.view_tag(name),
+                    None => Err(anyhow!("tag: missing tag name")),
+                };
+                self.send_result(win, result);
+            }
+            "window" => {
+                let result = self.window_command(command);
+                self.send_result(win, result);
+            }
+            "focus" => {
+                let result = self.focus_command(command);
+                self.send_result(win, result);
+            }
+            "swap" => {
+                let result = match command.first() {
+                    Some(dir) => dir.parse().and_then(|dir| self.swap_direction(dir)),
+                    None => Err(anyhow!("swap: missing direction")),
+                };
+                self.send_result(win, result);
+            }
+            "scratchpad" => {
+                let result = self.scratchpad_command(command);
+                self.send_result(win, result);
+            }
+            "rule" => {
+                // Rules do their own splitting, values may contain spaces
+                let result = self.rule_command(&line.trim_start()[cmd.len()..]);
+                self.send_result(win, result);
+            }
+            "presel" | "node" | "split" | "remove" | "focus_frame" => {
+                let mut args = vec![cmd];
+                args.extend(command);
+                let result = self.layout_specific_command(&args);
+                self.send_result(win, result);
+            }
+            "query" => {
+                match self.query(command) {
+                    Ok(data) => self.ipc_server.send_data(win, &data),
+                    Err(e) => self.send_result(win, Err(e)),
+                }
+            }
             "set" => {
-                let config = command.remove(0);
-                self.set_config(config, command);
-                self.ipc_server.send_reply(win, ipc::IPC_STATE_SUCCESS);
+                let result = match command.first() {
+                    Some(&config) => self.set_config(config, command[1..].to_vec()),
+                    None => Err(anyhow!("set: missing config name")),
+                };
+                self.send_result(win, result);
             }
             _ => {
                 self.ipc_server.send_reply(win, ipc::IPC_STATE_ERROR);
@@ -482,11 +944,956 @@ impl<'a> WindowManager<'a> {
         }
     }
     
+    /// `tag <name>`: show a tag on the monitor of the active tag, hiding the
+    /// tag that was there. The layout state of each tag is kept as is.
+    fn view_tag(&mut self, name: &str) -> Result<()> {
+        let tag = self
+            .tags
+            .iter()
+            .position(|t| t.name() == name)
+            .with_context(|| format!("tag: unknown tag {}", name))?;
+        self.show_tag(tag);
+        Ok(())
+    }
+
+    /// Make a tag the active one, showing it on the monitor of the active tag
+    /// if it isn't visible yet, and focus its last focused window
+    fn show_tag(&mut self, tag: TagId) {
+        if tag == self.active_workspace().active_tag() {
+            return;
+        }
+        let hidden = self.workspaces[self.active_workspace].active_view_mut().show_tag(tag);
+        if let Some(hidden) = hidden {
+            // Sticky windows stay on the monitor and go over to the new tag
+            let (sticky, others): (Vec<Window>, Vec<Window>) =
+                self.tags[hidden].windows().iter().partition(|&&w| self.is_sticky(w));
+            for win in others {
+                self.conn.unmap_window(win);
+            }
+            for &win in self.tags[tag].windows() {
+                self.conn.map_window(win);
+            }
+            for win in sticky {
+                self.tags[hidden].remove_window(win);
+                self.tags[tag].add_window(win);
+                if let Some(info) = self.windows.get_mut(&win) {
+                    info.set_tag(tag);
+                }
+            }
+        }
+        self.apply_layout(tag);
+        let focus = self.tags[tag].focused_window();
+        self.change_focus(focus);
+    }
+
+    /// Focus a window, showing its tag first and restoring it if needed
+    fn focus_anywhere(&mut self, win: Window) {
+        // A swallowed terminal stays out of sight until its window closes
+        if self.is_swallowed(win) {
+            return;
+        }
+        if let Some(tag) = self.windows.get(&win).map(|info| info.tag()) {
+            if self.is_hidden(win) {
+                self.restore_window(win);
+            }
+            self.show_tag(tag);
+            self.change_focus(Some(win));
+        }
+    }
+
+    /// `focus <left|right|up|down>`, `focus <next|prev>`, `focus last`,
+    /// `focus urgent`
+    fn focus_command(&mut self, args: Vec<&str>) -> Result<()> {
+        let arg = *args.first().context("focus: missing argument")?;
+        if arg == "urgent" {
+            let oldest = self.urgent_windows.iter().copied().find(|&w| !self.is_swallowed(w));
+            self.focus_anywhere(oldest.context("focus urgent: no urgent window")?);
+            return Ok(());
+        }
+        if arg == "last" {
+            let focused = self.focused_window();
+            let last = self.focus_history.iter().rev().copied().find(|&w| Some(w) != focused);
+            self.focus_anywhere(last.context("focus last: no previous window")?);
+            return Ok(());
+        }
+        if arg == "next" || arg == "prev" {
+            let windows = self.tags[self.active_workspace().active_tag()].windows();
+            let count = windows.len();
+            let current = self.focused_window().and_then(|f| windows.iter().position(|&w| w == f));
+            let index = match current {
+                Some(i) if arg == "next" => (i + 1) % count,
+                Some(i) => (i + count - 1) % count,
+                None => 0,
+            };
+            let win = windows.get(index).copied().context("focus: no window")?;
+            self.change_focus(Some(win));
+            return Ok(());
+        }
+        let dir: Direction = arg.parse()?;
+        let (tag, win) = self.window_in_direction(dir).with_context(|| format!("focus {}", arg))?;
+        self.activate_tag(tag);
+        self.change_focus(win);
+        Ok(())
+    }
+
+    /// `swap <left|right|up|down>`: exchange the focused window with the one
+    /// in that direction, which may be on another monitor. Moving towards an
+    /// empty monitor moves the window to the tag shown there.
+    fn swap_direction(&mut self, dir: Direction) -> Result<()> {
+        let win = self.focused_window().context("swap: no focused window")?;
+        let tag = self.windows.get(&win).context("swap: unknown window")?.tag();
+        let (target_tag, target) = self.window_in_direction(dir).context("swap")?;
+        match target {
+            Some(target) => {
+                self.tags[tag].swap_windows(win, target);
+                if target_tag != tag {
+                    self.tags[target_tag].swap_windows(win, target);
+                    if let Some(info) = self.windows.get_mut(&target) {
+                        info.set_tag(tag);
+                    }
+                }
+            }
+            None => {
+                self.tags[tag].remove_window(win);
+                self.tags[target_tag].add_window(win);
+            }
+        }
+        if let Some(info) = self.windows.get_mut(&win) {
+            info.set_tag(target_tag);
+        }
+        self.apply_layout(tag);
+        if target_tag != tag {
+            self.apply_layout(target_tag);
+            self.activate_tag(target_tag);
+        }
+        Ok(())
+    }
+
+    /**
+     * Find where a directional command leads, using the geometries of the
+     * windows as they are on screen, so that it works with any layout.
+     *
+     * Windows of the active tag are tried first, then the tag shown on the
+     * adjacent monitor in that direction. Returns the tag along with the
+     * nearest window in it, which is None if that tag has no windows.
+     */
+    fn window_in_direction(&self, dir: Direction) -> Result<(TagId, Option<Window>)> {
+        if let Some(win) = self.focused_window().filter(|&w| self.is_swallowed(w)) {
+            return Err(anyhow!("the focused window {} is swallowed", win));
+        }
+        let view = self.active_workspace().active_view();
+        let tag = view.active_tag();
+        let monitor = view.region_of(tag).context("the active tag is not shown")?;
+        let focused = self.focused_window().filter(|w| self.tags[tag].windows().contains(w));
+        let from = match focused {
+            Some(win) => self.conn.get_geometry(win)?,
+            None => monitor,
+        };
+        let nearest = |tag: TagId| -> Option<Window> {
+            let windows: Vec<Window> = self.tags[tag]
+                .windows()
+                .iter()
+                .copied()
+                .filter(|&w| Some(w) != focused)
+                .collect();
+            let geometries: Vec<Rectangle> = windows
+                .iter()
+                .map(|&w| self.conn.get_geometry(w))
+                .collect::<Result<_>>()
+                .ok()?;
+            find_in_direction(&from, &geometries, dir).map(|i| windows[i])
+        };
+        if let Some(win) = nearest(tag) {
+            return Ok((tag, Some(win)));
+        }
+        let others: Vec<(TagId, Rectangle)> = view
+            .tags()
+            .iter()
+            .filter(|&&t| t != tag)
+            .filter_map(|&t| view.region_of(t).map(|r| (t, r)))
+            .collect();
+        let regions: Vec<Rectangle> = others.iter().map(|&(_, r)| r).collect();
+        let next = find_in_direction(&monitor, &regions, dir).context("nothing in that direction")?;
+        Ok((others[next].0, nearest(others[next].0)))
+    }
+
+    /// Make a tag that is shown on a monitor of the active view the active one
+    fn activate_tag(&mut self, tag: TagId) {
+        if self.active_workspace().active_view().has_tag(tag) {
+            self.workspaces[self.active_workspace].active_view_mut().show_tag(tag);
+        }
+    }
+
+    /// `window floating <on|off|toggle> [window]`,
+    /// `window fullscreen <on|off|toggle> [window]`,
+    /// `window sticky <on|off|toggle> [window]`,
+    /// `window move <up|down>`, `window promote`,
+    /// `window hide [window]`, `window unhide <window|last>`
+    /// Acts on the focused window unless a window id is given.
+    fn window_command(&mut self, mut args: Vec<&str>) -> Result<()> {
+        if args.is_empty() {
+            return Err(anyhow!("window: missing sub-command"));
+        }
+        let sub = args.remove(0);
+        match sub {
+            "floating" => {
+                let value = *args.first().context("window floating: missing value")?;
+                let win = self.target_window(args.get(1).copied())?;
+                let floating = parse_switch(value, self.is_floating(win))?;
+                self.set_floating(win, floating);
+                Ok(())
+            }
+            "fullscreen" => {
+                let value = *args.first().context("window fullscreen: missing value")?;
+                let win = self.target_window(args.get(1).copied())?;
+                let fullscreen = parse_switch(value, self.is_fullscreen(win))?;
+                self.set_fullscreen(win, fullscreen);
+                Ok(())
+            }
+            "sticky" => {
+                let value = *args.first().context("window sticky: missing value")?;
+                let win = self.target_window(args.get(1).copied())?;
+                let sticky = parse_switch(value, self.is_sticky(win))?;
+                self.set_sticky(win, sticky);
+                Ok(())
+            }
+            "move" => {
+                let up = match args.first() {
+                    Some(&"up") => true,
+                    Some(&"down") => false,
+                    _ => return Err(anyhow!("window move: expected up or down")),
+                };
+                self.move_in_stack(up)
+            }
+            "promote" => self.promote_focused(),
+            "hide" => {
+                let win = self.target_window(args.first().copied())?;
+                self.hide_window(win);
+                Ok(())
+            }
+            "unhide" => {
+                let arg = *args.first().context("window unhide: missing window")?;
+                let win = if arg == "last" {
+                    *self.hidden_windows.last().context("window unhide: no hidden window")?
+                } else {
+                    parse_window(arg)?
+                };
+                if !self.is_hidden(win) {
+                    return Err(anyhow!("window unhide: window {} is not hidden", win));
+                }
+                self.focus_anywhere(win);
+                Ok(())
+            }
+            _ => Err(anyhow!("window: unknown sub-command {}", sub)),
+        }
+    }
+
+    /// The focused window if it is tiled, with its tag and the tiled windows
+    /// of that tag, for commands that reorder the stack
+    fn focused_tiled(&self) -> Result<(Window, TagId, Vec<Window>)> {
+        let win = self.focused_window().context("no focused window")?;
+        let tag = self.windows.get(&win).context("unknown window")?.tag();
+        let tiled = self.tiled_windows(tag);
+        if tiled.contains(&win) {
+            Ok((win, tag, tiled))
+        } else {
+            Err(anyhow!("window {} is not tiled", win))
+        }
+    }
+
+    /// Swap the focused window with the previous (up) or next tiled window of
+    /// its tag, wrapping around at the ends of the stack
+    fn move_in_stack(&mut self, up: bool) -> Result<()> {
+        let (win, tag, tiled) = self.focused_tiled()?;
+        let count = tiled.len();
+        let index = tiled.iter().position(|&w| w == win).unwrap_or(0);
+        let other = if up { tiled[(index + count - 1) % count] } else { tiled[(index + 1) % count] };
+        self.tags[tag].swap_windows(win, other);
+        self.apply_layout(tag);
+        Ok(())
+    }
+
+    /// Make the focused window the master (dwm's zoom). The master itself
+    /// changes places with the next tiled window instead.
+    fn promote_focused(&mut self) -> Result<()> {
+        let (win, tag, tiled) = self.focused_tiled()?;
+        if tiled[0] == win {
+            if let Some(&next) = tiled.get(1) {
+                self.tags[tag].swap_windows(win, next);
+                self.change_focus(Some(next));
+            }
+        } else {
+            self.tags[tag].promote(win, tiled[0]);
+        }
+        self.apply_layout(tag);
+        Ok(())
+    }
+
+    /// The window an ipc command acts on: the given id, or the focused window
+    fn target_window(&self, arg: Option<&str>) -> Result<Window> {
+        let win = match arg {
+            Some(arg) => parse_window(arg)?,
+            None => self.focused_window().context("no focused window")?,
+        };
+        if self.windows.contains_key(&win) {
+            Ok(win)
+        } else {
+            Err(anyhow!("unknown window {}", win))
+        }
+    }
+
+    /// Move a window between the floating and tiled state.
+    /// Floating windows stay in the window list of their tag, so a window
+    /// that is tiled again gets its previous place in the layout back.
+    fn set_floating(&mut self, win: Window, floating: bool) {
+        if self.is_floating(win) == floating {
+            return;
+        }
+        let geometry = if floating { None } else { self.conn.get_geometry(win).ok() };
+        let info = match self.windows.get_mut(&win) {
+            Some(info) => info,
+            None => return,
+        };
+        let tag = info.tag();
+        info.set_floating(floating);
+        let was_sticky = info.is_sticky();
+        if !floating {
+            // Only floating windows can be sticky
+            info.set_sticky(false);
+        }
+        if floating {
+            match info.floating_geometry() {
+                Some(g) => self.conn.configure_window(win, Some(g), None, Some(true)),
+                None => self.place_floating_default(win, tag),
+            }
+        } else {
+            // Remember where the window floated for the next time
+            info.set_floating_geometry(geometry);
+        }
+        if was_sticky && !floating {
+            self.update_net_wm_state(win);
+        }
+        self.apply_layout(tag);
+    }
+
+    /// Make a window sticky: it floats and keeps being shown on its monitor
+    /// whatever tag is shown there. It floats on when it stops being sticky.
+    fn set_sticky(&mut self, win: Window, sticky: bool) {
+        if self.is_sticky(win) == sticky {
+            return;
+        }
+        if sticky {
+            self.set_floating(win, true);
+        }
+        if let Some(info) = self.windows.get_mut(&win) {
+            info.set_sticky(sticky);
+            self.update_net_wm_state(win);
+        }
+    }
+
+    /// Make a window cover the monitor of its tag, without a border and above
+    /// every other window, or give it back its previous place
+    fn set_fullscreen(&mut self, win: Window, fullscreen: bool) {
+        if self.is_fullscreen(win) == fullscreen {
+            return;
+        }
+        let geometry = self.conn.get_geometry(win).ok();
+        let info = match self.windows.get_mut(&win) {
+            Some(info) => info,
+            None => return,
+        };
+        let tag = info.tag();
+        info.set_fullscreen(fullscreen);
+        if fullscreen {
+            // Tiled windows get their place back from the layout
+            if info.is_floating() {
+                info.set_floating_geometry(geometry);
+            }
+            self.conn.configure_window(win, None, Some(0), Some(true));
+        } else {
+            let floating_geometry = info.floating_geometry().filter(|_| info.is_floating());
+            self.conn.configure_window(win, floating_geometry, Some(self.config.border_width_px), None);
+        }
+        self.update_net_wm_state(win);
+        self.apply_layout(tag);
+    }
+
+    /// Whether a window is in one of the _NET_WM_STATE states we handle
+    fn has_state(&self, win: Window, state: NetWmState) -> bool {
+        match state {
+            NetWmState::Fullscreen => self.is_fullscreen(win),
+            NetWmState::DemandsAttention => self.is_urgent(win),
+            NetWmState::Hidden => self.is_hidden(win),
+            NetWmState::Sticky => self.is_sticky(win),
+        }
+    }
+
+    /// Enter or leave a _NET_WM_STATE state, as requested by a client
+    fn set_state(&mut self, win: Window, state: NetWmState, on: bool) {
+        match state {
+            NetWmState::Fullscreen => self.set_fullscreen(win, on),
+            NetWmState::DemandsAttention => self.set_urgent(win, on),
+            // Only the window manager sets this state, clients iconify
+            // through WM_CHANGE_STATE
+            NetWmState::Hidden => (),
+            NetWmState::Sticky => self.set_sticky(win, on),
+        }
+    }
+
+    fn is_urgent(&self, win: Window) -> bool {
+        self.urgent_windows.contains(&win)
+    }
+
+    /// A tag is urgent when one of its windows is
+    fn is_tag_urgent(&self, tag: TagId) -> bool {
+        self.tags[tag].windows().iter().any(|&w| self.is_urgent(w))
+    }
+
+    /**
+     * Mark a window as asking for attention, through the urgency flag of its
+     * WM_HINTS or _NET_WM_STATE_DEMANDS_ATTENTION, or clear that mark.
+     *
+     * The focused window never becomes urgent: the user is already on it.
+     */
+    fn set_urgent(&mut self, win: Window, urgent: bool) {
+        let urgent = urgent && self.focused_window() != Some(win) && !self.is_swallowed(win);
+        if self.is_urgent(win) == urgent {
+            return;
+        }
+        if urgent {
+            self.urgent_windows.push(win);
+            self.set_border(win, Border::Urgent);
+        } else {
+            self.urgent_windows.retain(|&w| w != win);
+            if self.focused_window() != Some(win) {
+                self.set_border(win, Border::Unfocused);
+            }
+        }
+        self.update_net_wm_state(win);
+    }
+
+    /// Paint the border of a window. Urgent windows keep their color until
+    /// they stop being urgent.
+    fn set_border(&self, win: Window, border: Border) {
+        let color = match border {
+            Border::Unfocused if self.is_urgent(win) => self.config.urgent_border_color,
+            Border::Urgent => self.config.urgent_border_color,
+            Border::Focused => self.config.focused_border_color,
+            Border::Unfocused => self.config.unfocused_border_color,
+        };
+        self.conn.set_window_border_color(win, color);
+    }
+
+    fn is_swallowed(&self, win: Window) -> bool {
+        self.swallowed.values().any(|&w| w == win)
+    }
+
+    /**
+     * The terminal a new window should swallow: a tiled window of the tag,
+     * of one of the swallow_classes, whose process started the new one.
+     *
+     * Terminals started from a terminal, transient windows and windows of the
+     * no_swallow_classes don't swallow anything.
+     */
+    fn swallow_target(&self, win: Window, properties: &WindowProperties, tag: TagId) -> Option<Window> {
+        let class = &properties.class;
+        if self.config.swallow_classes.is_empty()
+            || self.config.swallow_classes.contains(class)
+            || self.config.no_swallow_classes.contains(class)
+            || properties.transient
+        {
+            return None;
+        }
+        let pid = self.conn.get_wm_pid(win)?;
+        let is_terminal = |w: &Window| {
+            let class = self.windows.get(w).map(|info| info.wm_class());
+            self.config.swallow_classes.iter().any(|c| Some(c.as_str()) == class)
+        };
+        let terminals: Vec<(Window, u32)> = self
+            .tiled_windows(tag)
+            .into_iter()
+            .filter(is_terminal)
+            .filter_map(|w| self.conn.get_wm_pid(w).map(|pid| (w, pid)))
+            .collect();
+        // Walk up from the new window's process, the closest terminal wins
+        let mut pid = parent_pid(pid);
+        while let Some(current) = pid {
+            if let Some(&(terminal, _)) = terminals.iter().find(|&&(_, p)| p == current) {
+                return Some(terminal);
+            }
+            pid = parent_pid(current);
+        }
+        None
+    }
+
+    /// Put a new window in the place of a terminal and hide the terminal
+    /// until the window is closed
+    fn swallow(&mut self, terminal: Window, win: Window) {
+        let tag = match self.windows.get(&terminal) {
+            Some(info) => info.tag(),
+            None => return,
+        };
+        self.tags[tag].swap_windows(terminal, win);
+        self.tags[tag].remove_window(terminal);
+        // The window takes over the focus of the terminal too, even when it
+        // wouldn't be focused otherwise
+        if self.focused_window() == Some(terminal) {
+            self.change_focus(Some(win));
+        }
+        self.set_urgent(terminal, false);
+        self.focus_history.retain(|&w| w != terminal);
+        self.swallowed.insert(win, terminal);
+        self.conn.unmap_window(terminal);
+    }
+
+    /// Give a swallowed terminal back the place of the window that swallowed
+    /// it, on the tag of that window
+    fn release_swallowed(&mut self, terminal: Window, win: Window, in_layout: bool) {
+        let tag = match self.windows.get(&win) {
+            Some(info) => info.tag(),
+            None => return,
+        };
+        if let Some(info) = self.windows.get_mut(&terminal) {
+            info.set_tag(tag);
+        }
+        self.tags[tag].add_window(terminal);
+        if in_layout {
+            self.tags[tag].swap_windows(win, terminal);
+        }
+        if self.active_workspace().active_view().has_tag(tag) {
+            self.conn.map_window(terminal);
+        }
+    }
+
+    fn is_hidden(&self, win: Window) -> bool {
+        self.hidden_windows.contains(&win)
+    }
+
+    /// Iconify a window: unmap it and take it out of the layout of its tag.
+    /// It stays managed, so that it can be restored on the same tag later.
+    fn hide_window(&mut self, win: Window) {
+        let tag = match self.windows.get(&win) {
+            Some(info) if !self.is_hidden(win) && !self.is_swallowed(win) => info.tag(),
+            _ => return,
+        };
+        if self.drag.is_some_and(|drag| drag.win == win) {
+            self.drag = None;
+        }
+        self.tags[tag].remove_window(win);
+        self.hidden_windows.push(win);
+        // Only restoring it should bring it back, not `focus last`
+        self.focus_history.retain(|&w| w != win);
+        self.conn.unmap_window(win);
+        self.conn.set_iconic(win, true);
+        self.update_net_wm_state(win);
+        self.apply_layout(tag);
+        if self.focused_window() == Some(win) {
+            let next = self.tags[tag].focused_window();
+            self.change_focus(next);
+        }
+    }
+
+    /// Put a hidden window back at the end of the stack of its tag, and map
+    /// it if that tag is visible. Focusing it is left to the caller.
+    fn restore_window(&mut self, win: Window) {
+        let tag = match self.windows.get(&win) {
+            Some(info) if self.is_hidden(win) => info.tag(),
+            _ => return,
+        };
+        self.hidden_windows.retain(|&w| w != win);
+        self.tags[tag].add_window(win);
+        self.conn.set_iconic(win, false);
+        self.update_net_wm_state(win);
+        if self.active_workspace().active_view().has_tag(tag) {
+            self.conn.map_window(win);
+            self.apply_layout(tag);
+        }
+    }
+
+    /// `rule add <conditions and consequences...>`, `rule remove <id|all>`
+    /// See rules::Rule for the syntax. `query rules` lists the rules.
+    fn rule_command(&mut self, args: &str) -> Result<()> {
+        let args = args.trim_start();
+        let (sub, rest) = args.split_at(args.find(char::is_whitespace).unwrap_or(args.len()));
+        let args: Vec<&str> = rest.split_whitespace().collect();
+        match sub {
+            "" => Err(anyhow!("rule: missing sub-command")),
+            "add" => {
+                let rule = Rule::parse(self.next_rule_id, rest)?;
+                self.next_rule_id += 1;
+                self.rules.push(rule);
+                Ok(())
+            }
+            "remove" => {
+                let arg = *args.first().context("rule remove: missing rule id")?;
+                if arg == "all" {
+                    self.rules.clear();
+                    return Ok(());
+                }
+                let id: usize = arg.parse()?;
+                let index = self
+                    .rules
+                    .iter()
+                    .position(|rule| rule.id() == id)
+                    .with_context(|| format!("rule remove: no rule {}", id))?;
+                self.rules.remove(index);
+                Ok(())
+            }
+            _ => Err(anyhow!("rule: unknown sub-command {}", sub)),
+        }
+    }
+
+    /// `scratchpad add <name> [window]`,
+    /// `scratchpad add <name> class <class> <command...>`,
+    /// `scratchpad toggle <name>`, `scratchpad remove <name>`
+    fn scratchpad_command(&mut self, mut args: Vec<&str>) -> Result<()> {
+        if args.len() < 2 {
+            return Err(anyhow!("scratchpad: expected a sub-command and a name"));
+        }
+        let sub = args.remove(0);
+        let name = args.remove(0);
+        match sub {
+            "add" => {
+                let scratchpad = match args.first() {
+                    Some(&"class") => {
+                        let class = *args.get(1).context("scratchpad add: missing class")?;
+                        if args.len() < 3 {
+                            return Err(anyhow!("scratchpad add: missing command"));
+                        }
+                        Scratchpad::from_command(name, class, &args[2..].join(" "))
+                    }
+                    arg => Scratchpad::from_window(name, self.target_window(arg.copied())?),
+                };
+                self.scratchpads.retain(|s| s.name() != name);
+                self.scratchpads.push(scratchpad);
+                Ok(())
+            }
+            "remove" => {
+                let count = self.scratchpads.len();
+                self.scratchpads.retain(|s| s.name() != name);
+                if self.scratchpads.len() == count {
+                    return Err(anyhow!("scratchpad: unknown scratchpad {}", name));
+                }
+                Ok(())
+            }
+            "toggle" => self.toggle_scratchpad(name),
+            _ => Err(anyhow!("scratchpad: unknown sub-command {}", sub)),
+        }
+    }
+
+    /**
+     * Hide a scratchpad if it is shown on the active tag, or bring it there.
+     *
+     * A scratchpad without a window first captures an existing window of its
+     * class, or starts its command: the window is then shown when it maps.
+     */
+    fn toggle_scratchpad(&mut self, name: &str) -> Result<()> {
+        let index = self
+            .scratchpads
+            .iter()
+            .position(|s| s.name() == name)
+            .with_context(|| format!("scratchpad: unknown scratchpad {}", name))?;
+        let win = match self.scratchpads[index].window() {
+            Some(win) => win,
+            None => {
+                let existing = self.scratchpads[index]
+                    .class()
+                    .and_then(|class| self.scratchpad_candidate(class));
+                let scratchpad = &mut self.scratchpads[index];
+                match (existing, scratchpad.command()) {
+                    (Some(win), _) => {
+                        scratchpad.set_window(Some(win));
+                        win
+                    }
+                    (None, Some(command)) => {
+                        if !scratchpad.is_spawned() {
+                            spawn(command);
+                            scratchpad.set_spawned();
+                        }
+                        return Ok(());
+                    }
+                    (None, None) => return Err(anyhow!("scratchpad: the window of {} is gone", name)),
+                }
+            }
+        };
+        if self.is_swallowed(win) {
+            return Err(anyhow!("scratchpad: the window of {} is swallowed", name));
+        }
+        let tag = self.active_workspace().active_tag();
+        let shown = !self.is_hidden(win) && self.windows.get(&win).is_some_and(|info| info.tag() == tag);
+        if shown {
+            self.hide_window(win);
+        } else {
+            self.summon_window(win);
+        }
+        Ok(())
+    }
+
+    /// The window a scratchpad of the given class captures: hidden windows
+    /// first, in the order they were hidden, then the windows of the tags in
+    /// tag and stack order. Swallowed windows are in neither list, and windows
+    /// held by another scratchpad are left alone.
+    fn scratchpad_candidate(&self, class: &str) -> Option<Window> {
+        let held: Vec<Window> = self.scratchpads.iter().filter_map(|s| s.window()).collect();
+        self.hidden_windows
+            .iter()
+            .chain(self.tags.iter().flat_map(|tag| tag.windows()))
+            .copied()
+            .filter(|win| !held.contains(win))
+            .find(|win| self.windows.get(win).is_some_and(|info| info.wm_class() == class))
+    }
+
+    /// Bring a window to the active tag, floating in the middle of its
+    /// monitor, and focus it
+    fn summon_window(&mut self, win: Window) {
+        if self.is_swallowed(win) {
+            return;
+        }
+        let tag = self.active_workspace().active_tag();
+        let from = match self.windows.get_mut(&win) {
+            Some(info) => {
+                let from = info.tag();
+                info.set_tag(tag);
+                from
+            }
+            None => return,
+        };
+        if self.is_hidden(win) {
+            self.restore_window(win);
+        } else if from != tag {
+            self.tags[from].remove_window(win);
+            self.tags[tag].add_window(win);
+            self.apply_layout(from);
+            self.conn.map_window(win);
+        }
+        self.set_floating(win, true);
+        self.center_floating(win, tag);
+        self.apply_layout(tag);
+        self.change_focus(Some(win));
+    }
+
+    /// Publish the state of a window in its _NET_WM_STATE property
+    fn update_net_wm_state(&self, win: Window) {
+        let states: Vec<NetWmState> = NetWmState::ALL
+            .iter()
+            .copied()
+            .filter(|&state| self.has_state(win, state))
+            .collect();
+        self.conn.set_net_wm_state(win, &states);
+    }
+
+    /// Give a window that never floated a default floating geometry:
+    /// half the size of the monitor of its tag, centered on it
+    fn place_floating_default(&self, win: Window, tag: TagId) {
+        if let Some(region) = self.active_workspace().active_view().region_of(tag) {
+            let (_, _, w, h) = region.values();
+            let border = 2 * self.config.border_width_px as i32;
+            let geometry = region.center(w / 2, h / 2).resize(-border, -border);
+            self.conn.configure_window(win, Some(geometry), None, Some(true));
+        }
+    }
+
+    /// Reply to an ipc client with the outcome of a command
+    fn send_result(&self, win: Window, result: Result<()>) {
+        match result {
+            Ok(()) => self.ipc_server.send_reply(win, ipc::IPC_STATE_SUCCESS),
+            Err(e) => {
+                warn!("ipc command failed: {:?}", e);
+                self.ipc_server.send_reply(win, ipc::IPC_STATE_ERROR);
+            }
+        }
+    }
+
+    /// `layout set <name>`, `layout next`, `layout prev`,
+    /// `layout ratio <[+-]value>`, `layout nmaster <[+-]value>`,
+    /// `layout gaps <inner> [outer]`, `layout gaps default`,
+    /// `layout mirror <x|y> [on|off|toggle]`, `layout rotate [on|off|toggle]`
+    /// Other sub-commands are passed to the layout itself.
+    /// Acts on the active tag and re-tiles it.
+    fn layout_command(&mut self, mut args: Vec<&str>) -> Result<()> {
+        if args.is_empty() {
+            return Err(anyhow!("layout: missing sub-command"));
+        }
+        let sub = args.remove(0);
+        let arg = args.first().copied();
+        let tag = self.active_workspace().active_tag();
+        match sub {
+            "set" => {
+                let name = arg.context("layout set: missing layout name")?;
+                let layout = self
+                    .layouts
+                    .index_of(name)
+                    .and_then(|i| self.layouts.build(i))
+                    .with_context(|| format!("layout set: unknown layout {}", name))?;
+                self.tags[tag].set_layout(layout);
+            }
+            "next" | "prev" => {
+                let count = self.layouts.len();
+                let current = self.layouts.index_of(self.tags[tag].layout().name()).unwrap_or(0);
+                let index = if sub == "next" {
+                    (current + 1) % count
+                } else {
+                    (current + count - 1) % count
+                };
+                let layout = self.layouts.build(index).context("layout: no layout registered")?;
+                self.tags[tag].set_layout(layout);
+            }
+            "gaps" => {
+                let gaps = match args.as_slice() {
+                    ["default"] => None,
+                    [inner] => Some(Gaps { inner: inner.parse()?, outer: self.config.outer_gap_px }),
+                    [inner, outer] => Some(Gaps { inner: inner.parse()?, outer: outer.parse()? }),
+                    _ => return Err(anyhow!("layout gaps: expected <inner> [outer] or default")),
+                };
+                self.tags[tag].set_gaps(gaps);
+            }
+            "ratio" => {
+                let arg = arg.context("layout ratio: missing value")?;
+                let params = self.tags[tag].layout_params_mut();
+                let ratio = parse_relative(arg, params.main_ratio, self.config.main_ratio_step)?;
+                params.main_ratio = ratio.clamp(MIN_MAIN_RATIO, MAX_MAIN_RATIO);
+            }
+            "mirror" => {
+                let transform = self.tags[tag].transform_mut();
+                let (flag, value) = match args.as_slice() {
+                    ["x"] | ["x", _] => (&mut transform.mirror_x, args.get(1)),
+                    ["y"] | ["y", _] => (&mut transform.mirror_y, args.get(1)),
+                    _ => return Err(anyhow!("layout mirror: expected x|y [on|off|toggle]")),
+                };
+                *flag = parse_switch(value.copied().unwrap_or("toggle"), *flag)?;
+            }
+            "rotate" => {
+                let transform = self.tags[tag].transform_mut();
+                transform.rotate = parse_switch(arg.unwrap_or("toggle"), transform.rotate)?;
+            }
+            "nmaster" => {
+                let arg = arg.context("layout nmaster: missing value")?;
+                let params = self.tags[tag].layout_params_mut();
+                let nmaster = parse_relative(arg, params.nmaster as f32, 1.0)?;
+                params.nmaster = nmaster.round().max(0.0) as u32;
+            }
+            _ => {
+                // Anything else is specific to the layout of the tag
+                let mut layout_args = vec![sub];
+                layout_args.extend(args);
+                self.layout_specific_command(&layout_args)?;
+            }
+        }
+        self.apply_layout(tag);
+        Ok(())
+    }
+
+    /// Pass a command to the layout of the active tag and re-tile it
+    fn layout_specific_command(&mut self, args: &[&str]) -> Result<()> {
+        let tag = self.active_workspace().active_tag();
+        let focused = self.focused_window();
+        // Directions are given as seen on screen, layouts work before the transform
+        let args = self.tags[tag].transform().layout_args(args);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let focus = self.tags[tag].layout_mut().command(&args, focused)?;
+        self.apply_layout(tag);
+        if focus.is_some() {
+            self.change_focus(focus);
+        }
+        Ok(())
+    }
+
+    /// `query <what>`, returning the answer as text
+    fn query(&self, args: Vec<&str>) -> Result<String> {
+        let what = *args.first().context("query: missing argument")?;
+        match what {
+            "layout" => {
+                let tag_id = self.active_workspace().active_tag();
+                let tag = &self.tags[tag_id];
+                let status = tag.layout().status(&self.tiled_windows(tag_id), self.focused_window());
+                let transform = tag.transform().status();
+                if transform.is_empty() {
+                    Ok(status)
+                } else {
+                    Ok(format!("{} ({})", status, transform))
+                }
+            }
+            "tags" => {
+                // One line per tag: name followed by its flags
+                let view = self.active_workspace().active_view();
+                let lines: Vec<String> = self
+                    .tags
+                    .iter()
+                    .enumerate()
+                    .map(|(i, tag)| {
+                        let mut line = tag.name().to_string();
+                        if i == view.active_tag() {
+                            line.push_str(" active");
+                        } else if view.has_tag(i) {
+                            line.push_str(" visible");
+                        }
+                        if self.is_tag_urgent(i) {
+                            line.push_str(" urgent");
+                        }
+                        line
+                    })
+                    .collect();
+                Ok(lines.join("\n"))
+            }
+            "rules" => {
+                let lines: Vec<String> = self.rules.iter().map(|rule| rule.to_string()).collect();
+                Ok(lines.join("\n"))
+            }
+            "hidden" => {
+                // One line per hidden window, the most recently hidden first
+                let lines: Vec<String> = self
+                    .hidden_windows
+                    .iter()
+                    .rev()
+                    .filter_map(|win| self.windows.get(win))
+                    .map(|info| format!("0x{:x} {} {}", info.id(), info.wm_class(), info.wm_name()))
+                    .collect();
+                Ok(lines.join("\n"))
+            }
+            _ => Err(anyhow!("query: unknown argument {}", what)),
+        }
+    }
+
+    /// `set <config> <value>`
     fn set_config(&mut self, config: &str, args: Vec<&str>) -> Result<()> {
-        self.config.border_width_px = args[0].parse()?;
-        for &win in self.windows.keys() {
-            self.conn.configure_window(win, None, Some(self.config.border_width_px), None);
+        let value = *args.first().with_context(|| format!("set {}: missing value", config))?;
+        match config {
+            "border_width" => {
+                self.config.border_width_px = value.parse()?;
+                for &win in self.windows.keys() {
+                    if !self.is_fullscreen(win) {
+                        self.conn.configure_window(win, None, Some(self.config.border_width_px), None);
+                    }
+                }
+            }
+            "focused_border_color" => self.config.focused_border_color = parse_color(value)?,
+            "unfocused_border_color" => self.config.unfocused_border_color = parse_color(value)?,
+            "urgent_border_color" => self.config.urgent_border_color = parse_color(value)?,
+            "swallow_classes" => self.config.swallow_classes = parse_list(value),
+            "no_swallow_classes" => self.config.no_swallow_classes = parse_list(value),
+            "gap" => self.config.gap_px = value.parse()?,
+            "outer_gap" => self.config.outer_gap_px = value.parse()?,
+            "smart_gaps" => self.config.smart_gaps = parse_switch(value, self.config.smart_gaps)?,
+            "size_hints" => self.config.size_hints = parse_switch(value, self.config.size_hints)?,
+            "focus_follows_mouse" => {
+                self.config.focus_follows_mouse = parse_switch(value, self.config.focus_follows_mouse)?
+            }
+            "mouse_modifier" => {
+                self.config.mouse_modifier = parse_modifiers(value)
+                    .with_context(|| format!("set mouse_modifier: invalid modifiers {}", value))?;
+                self.grab_buttons();
+            }
+            "move_button" => {
+                self.config.move_button = value.parse()?;
+                self.grab_buttons();
+            }
+            "resize_button" => {
+                self.config.resize_button = value.parse()?;
+                self.grab_buttons();
+            }
+            _ => return Err(anyhow!("set: unknown config {}", config)),
         }
+        self.apply_visible_layouts();
         Ok(())
     }
 }
@@ -500,16 +1907,41 @@ pub struct Config {
     pub tags: Vec<String>,
     /// _NET_WM_WINDOW_TYPE_XXX values that should always be treated as floating.
     pub floating_window_types: &'static [&'static str],
+    /// WM_CLASS of the terminals that are swallowed by the windows they start
+    pub swallow_classes: Vec<String>,
+    /// WM_CLASS of the windows that never swallow their terminal
+    pub no_swallow_classes: Vec<String>,
     /// Focused boder color
     pub focused_border_color: u32,
     /// Unfocused boder color
     pub unfocused_border_color: u32,
+    /// Border color of windows that ask for attention
+    pub urgent_border_color: u32,
     /// The width of window borders in pixels
     pub border_width_px: u32,
     /// The size of gaps between windows in pixels.
     pub gap_px: u32,
+    /// The size of gaps between windows and the monitor edges in pixels.
+    pub outer_gap_px: u32,
+    /// Drop all gaps on tags that only have a single tiled window.
+    pub smart_gaps: bool,
+    /// Respect the WM_NORMAL_HINTS of tiled windows (min/max size, increments,
+    /// aspect ratio). Windows that don't fill their slot are centered in it.
+    pub size_hints: bool,
+    /// Focus windows when the mouse pointer enters them.
+    pub focus_follows_mouse: bool,
+    /// Modifiers to hold while dragging windows with the mouse, as an X modifier mask.
+    pub mouse_modifier: u16,
+    /// Mouse button that moves a window when dragged with mouse_modifier held.
+    pub move_button: u8,
+    /// Mouse button that resizes a window when dragged with mouse_modifier held.
+    pub resize_button: u8,
+    /// The default fraction of the screen taken by the main area.
+    pub main_ratio: f32,
     /// The percentage change in main_ratio to be applied when increasing / decreasing.
     pub main_ratio_step: f32,
+    /// The default number of windows in the main area.
+    pub nmaster: u32,
     /// Whether or not space should be reserved for a status bar
     pub show_bar: bool,
     /// True if the status bar should be at the top of the screen, false if it should be at the bottom
@@ -518,6 +1950,69 @@ pub struct Config {
     pub bar_height: u32,
 }
 
+/// Parse an ipc argument that is either an absolute value ("0.6") or a change
+/// relative to the current value ("+0.05", "-1"). A bare sign applies `step`.
+fn parse_relative(arg: &str, current: f32, step: f32) -> Result<f32> {
+    let value = |s: &str| -> Result<f32> {
+        if s.is_empty() {
+            Ok(step)
+        } else {
+            s.parse().with_context(|| format!("invalid number {}", arg))
+        }
+    };
+    if let Some(delta) = arg.strip_prefix('+') {
+        Ok(current + value(delta)?)
+    } else if let Some(delta) = arg.strip_prefix('-') {
+        Ok(current - value(delta)?)
+    } else {
+        value(arg)
+    }
+}
+
+/// Parse a window id given in decimal or in hexadecimal with a 0x prefix,
+/// as printed by xwininfo / xprop
+fn parse_window(arg: &str) -> Result<Window> {
+    let win = match arg.strip_prefix("0x") {
+        Some(hex) => Window::from_str_radix(hex, 16),
+        None => arg.parse(),
+    };
+    win.with_context(|| format!("invalid window id {}", arg))
+}
+
+/// Parse a color given as #rrggbb, 0xrrggbb or rrggbb
+fn parse_color(arg: &str) -> Result<u32> {
+    let hex = arg.trim_start_matches('#').trim_start_matches("0x");
+    u32::from_str_radix(hex, 16).with_context(|| format!("invalid color {}", arg))
+}
+
+/// Parse a comma separated list, where "none" is the empty list
+fn parse_list(arg: &str) -> Vec<String> {
+    match arg {
+        "none" => Vec::new(),
+        _ => arg.split(',').filter(|s| !s.is_empty()).map(String::from).collect(),
+    }
+}
+
+/// The parent of a process, read from /proc/<pid>/stat
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The process name comes in parentheses and may contain anything, so the
+    // fields after it are found from the last parenthesis: state, then ppid
+    let fields = &stat[stat.rfind(')')? + 1..];
+    let ppid = fields.split_whitespace().nth(1)?.parse().ok()?;
+    if ppid > 1 { Some(ppid) } else { None }
+}
+
+/// Parse an ipc switch argument: on/off/true/false/1/0, or toggle to flip `current`.
+pub(crate) fn parse_switch(arg: &str, current: bool) -> Result<bool> {
+    match arg {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        "toggle" => Ok(!current),
+        _ => Err(anyhow!("invalid switch value {}", arg)),
+    }
+}
+
 macro_rules! vec_of_strings {
     ($($x:expr),*) => (vec![$($x.to_string()),*]);
 }
@@ -529,18 +2024,26 @@ impl Config {
             workspaces: vec_of_strings!["1", "2", "3", "4", "5", "6", "7", "8", "9"],
             tags : vec_of_strings!["1", "2", "3", "4", "5", "6", "7", "8", "9"],
             floating_window_types: &["DIALOG", "UTILITY", "SPLASH"],
+            swallow_classes: Vec::new(),
+            no_swallow_classes: Vec::new(),
             focused_border_color: 0xcc241d,   // #cc241d
             unfocused_border_color: 0x3c3836, // #3c3836
+            urgent_border_color: 0xd79921,    // #d79921
             border_width_px: 2,
             gap_px: 5,
+            outer_gap_px: 5,
+            smart_gaps: false,
+            size_hints: true,
+            focus_follows_mouse: false,
+            mouse_modifier: xcb::MOD_MASK_4 as u16,
+            move_button: 1,
+            resize_button: 3,
+            main_ratio: 0.6,
             main_ratio_step: 0.05,
+            nmaster: 1,
             show_bar: true,
             top_bar: true,
             bar_height: 18,
         }
     }
 }
-
-// hc rule windowtype~'_NET_WM_WINDOW_TYPE_(DIALOG|UTILITY|SPLASH)' floating=on
-// hc rule windowtype='_NET_WM_WINDOW_TYPE_DIALOG' focus=on
-// hc rule windowtype~'_NET_WM_WINDOW_TYPE_(NOTIFICATION|DOCK|DESKTOP)' manage=off
@@ -29,4 +29,8 @@ impl Workspace {
     pub fn active_view(&self) -> &View {
         &self.views[self.active_view]
     }
+
+    pub fn active_view_mut(&mut self) -> &mut View {
+        &mut self.views[self.active_view]
+    }
 }
@@ -1,4 +1,5 @@
 // use std::{cell::Cell, collections::HashMap, convert::TryFrom, convert::TryInto};
+use std::cell::Cell;
 use anyhow::{Result, Context, anyhow};
 
 use xcb::{Window, Atom, xinerama, randr};
@@ -20,6 +21,9 @@ const NEW_WINDOW_MASK: &[(u32, u32)] = &[(
     xcb::EVENT_MASK_ENTER_WINDOW | xcb::EVENT_MASK_LEAVE_WINDOW | xcb::EVENT_MASK_PROPERTY_CHANGE,
 )];
 // const INPUT_FOCUS_PARENT: u8 = xcb::INPUT_FOCUS_PARENT as u8;
+const MOUSE_MASK: u16 = (xcb::EVENT_MASK_BUTTON_PRESS
+    | xcb::EVENT_MASK_BUTTON_RELEASE
+    | xcb::EVENT_MASK_POINTER_MOTION) as u16;
 const INPUT_FOCUS_POINTER_ROOT: u8 = xcb::INPUT_FOCUS_POINTER_ROOT as u8;
 const PROP_MODE_REPLACE: u8 = xcb::PROP_MODE_REPLACE as u8;
 
@@ -31,6 +35,15 @@ const CONFIG_WINDOW_Y: u16 = xcb::CONFIG_WINDOW_Y as u16;
 const CONFIG_WINDOW_STACK_MODE: u16 = xcb::CONFIG_WINDOW_STACK_MODE as u16;
 const CONFIG_WINDOW_STACK_ABOVE: u32 = xcb::STACK_MODE_ABOVE as u32;
 
+// WM_NORMAL_HINTS flags (ICCCM 4.1.2.3)
+const SIZE_HINT_P_MIN_SIZE: u32 = 1 << 4;
+const SIZE_HINT_P_MAX_SIZE: u32 = 1 << 5;
+const SIZE_HINT_P_RESIZE_INC: u32 = 1 << 6;
+const SIZE_HINT_P_ASPECT: u32 = 1 << 7;
+const SIZE_HINT_BASE_SIZE: u32 = 1 << 8;
+// WM_HINTS flags (ICCCM 4.1.2.4)
+const WM_HINT_X_URGENCY: u32 = 1 << 8;
+
 // TODO: use strum?
 macro_rules! atoms {
     ( $( $name:ident ),+ ) => {
@@ -56,7 +69,7 @@ macro_rules! atoms {
 }
 
 // Intern atoms that are not built-in in icccm or ewmh
-atoms!(WM_DELETE_WINDOW, UTF8_STRING);
+atoms!(WM_DELETE_WINDOW, UTF8_STRING, WM_CHANGE_STATE, WM_WINDOW_ROLE);
 
 /// An X key-code along with a modifier mask
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
@@ -112,6 +125,117 @@ impl Rectangle {
     pub fn values(&self) -> (i32, i32, u32, u32) {
         (self.x, self.y, self.w, self.h)
     }
+
+    /// A rectangle of the given size centered on this one
+    pub fn center(&self, w: u32, h: u32) -> Rectangle {
+        let x = self.x + (self.w as i32 - w as i32) / 2;
+        let y = self.y + (self.h as i32 - h as i32) / 2;
+        Rectangle::new(x, y, w, h)
+    }
+
+    /// Move every edge inwards by px (outwards if negative).
+    /// The result is never smaller than 1x1.
+    pub fn shrink(&self, px: i32) -> Rectangle {
+        Rectangle::new(self.x + px, self.y + px, self.w, self.h).resize(-2 * px, -2 * px)
+    }
+
+    /// Change the width and height by dw and dh, keeping the top left corner.
+    /// The result is never smaller than 1x1.
+    pub fn resize(&self, dw: i32, dh: i32) -> Rectangle {
+        let w = (self.w as i32 + dw).max(1) as u32;
+        let h = (self.h as i32 + dh).max(1) as u32;
+        Rectangle::new(self.x, self.y, w, h)
+    }
+}
+
+/// The WM_NORMAL_HINTS constraints of a window that matter when tiling it.
+/// All sizes are in pixels and exclude the border.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct SizeHints {
+    /// Minimum (width, height)
+    pub min: Option<(u32, u32)>,
+    /// Maximum (width, height)
+    pub max: Option<(u32, u32)>,
+    /// Size that the increments are counted from
+    pub base: Option<(u32, u32)>,
+    /// Preferred size steps, e.g. the cell size of a terminal
+    pub inc: Option<(u32, u32)>,
+    /// Minimum and maximum aspect ratios, each as (numerator, denominator)
+    pub aspect: Option<((u32, u32), (u32, u32))>,
+}
+
+impl SizeHints {
+    /// Adjust a size to the hints, following ICCCM 4.1.2.3: the aspect ratio
+    /// and increments apply to the size minus the base size, then the result
+    /// is bounded by the min and max sizes. The min size wins over the given
+    /// size, so the result may be larger than requested.
+    pub fn constrain(&self, w: u32, h: u32) -> (u32, u32) {
+        // The base size defaults to the min size and vice versa
+        let (base_w, base_h) = self.base.or(self.min).unwrap_or((0, 0));
+        let (min_w, min_h) = self.min.or(self.base).unwrap_or((1, 1));
+        let mut w = w.saturating_sub(base_w);
+        let mut h = h.saturating_sub(base_h);
+
+        if let Some(((min_n, min_d), (max_n, max_d))) = self.aspect {
+            if w > 0 && h > 0 {
+                let ratio = w as f64 / h as f64;
+                if max_n > 0 && max_d > 0 && ratio > max_n as f64 / max_d as f64 {
+                    w = (h as f64 * max_n as f64 / max_d as f64).round() as u32;
+                } else if min_n > 0 && min_d > 0 && ratio < min_n as f64 / min_d as f64 {
+                    h = (w as f64 * min_d as f64 / min_n as f64).round() as u32;
+                }
+            }
+        }
+        if let Some((inc_w, inc_h)) = self.inc {
+            if inc_w > 0 {
+                w -= w % inc_w;
+            }
+            if inc_h > 0 {
+                h -= h % inc_h;
+            }
+        }
+
+        let mut w = (w + base_w).max(min_w);
+        let mut h = (h + base_h).max(min_h);
+        if let Some((max_w, max_h)) = self.max {
+            if max_w > 0 {
+                w = w.min(max_w.max(min_w));
+            }
+            if max_h > 0 {
+                h = h.min(max_h.max(min_h));
+            }
+        }
+        (w.max(1), h.max(1))
+    }
+
+    /// Fit a window into a tiled slot: the size is constrained by the hints
+    /// and the window is centered in the slot when it doesn't fill it.
+    pub fn fit(&self, slot: &Rectangle) -> Rectangle {
+        let (w, h) = self.constrain(slot.w, slot.h);
+        slot.center(w, h)
+    }
+}
+
+/// The _NET_WM_STATE values that the window manager handles
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NetWmState {
+    /// _NET_WM_STATE_FULLSCREEN
+    Fullscreen,
+    /// _NET_WM_STATE_DEMANDS_ATTENTION
+    DemandsAttention,
+    /// _NET_WM_STATE_HIDDEN
+    Hidden,
+    /// _NET_WM_STATE_STICKY
+    Sticky,
+}
+
+impl NetWmState {
+    pub const ALL: &'static [NetWmState] = &[
+        NetWmState::Fullscreen,
+        NetWmState::DemandsAttention,
+        NetWmState::Hidden,
+        NetWmState::Sticky,
+    ];
 }
 
 /// X window border kind
@@ -227,11 +351,27 @@ pub enum XEvent {
         data: [u8; 20],
     },
 
-    // /// xcb docs: https://www.mankier.com/3/xcb_input_raw_button_press_event_t
-    // ButtonPress,
+    /// xcb docs: https://www.mankier.com/3/xcb_button_press_event_t
+    ButtonPress {
+        /// The top level window under the pointer, xcb::NONE for the root window
+        id: Window,
+        /// The button that was pressed
+        button: u8,
+        /// Absolute coordinate of the event
+        rpt: Point,
+    },
+
+    /// xcb docs: https://www.mankier.com/3/xcb_button_release_event_t
+    ButtonRelease {
+        /// The button that was released
+        button: u8,
+    },
 
-    // /// xcb docs: https://www.mankier.com/3/xcb_input_raw_button_press_event_t
-    // ButtonRelease,
+    /// xcb docs: https://www.mankier.com/3/xcb_motion_notify_event_t
+    MotionNotify {
+        /// Absolute coordinate of the pointer
+        rpt: Point,
+    },
 
     /// xcb docs: https://www.mankier.com/3/xcb_input_device_key_press_event_t
     KeyPress {
@@ -247,15 +387,15 @@ pub enum XEvent {
         ignore: bool,
     },
 
-    // /// xcb docs: https://www.mankier.com/3/xcb_enter_notify_event_t
-    // Enter {
-    //     /// The ID of the window that was entered
-    //     id: Window,
-    //     /// Absolute coordinate of the event
-    //     rpt: Point,
-    //     /// Coordinate of the event relative to top-left of the window itself
-    //     wpt: Point,
-    // },
+    /// xcb docs: https://www.mankier.com/3/xcb_enter_notify_event_t
+    Enter {
+        /// The ID of the window that was entered
+        id: Window,
+        /// Absolute coordinate of the event
+        rpt: Point,
+        /// Coordinate of the event relative to top-left of the window itself
+        wpt: Point,
+    },
 
     // /// xcb docs: https://www.mankier.com/3/xcb_enter_notify_event_t
     // Leave {
@@ -335,7 +475,11 @@ pub struct XcbConnection {
     preferred_screen: i32,
     root: Window,
     atoms: InternedAtoms,
-    // check_win: Window,
+    /// Enter events older than this request were caused by our own changes,
+    /// see ignore_crossing_events
+    crossing_barrier: Cell<u16>,
+    /// The _NET_SUPPORTING_WM_CHECK window, created by register_wm
+    check_win: Cell<Window>,
     // auto_float_types: Vec<&'static str>,
     // randr_base: u8,
 }
@@ -384,6 +528,8 @@ impl XcbConnection {
             root,
             // check_win,
             atoms,
+            crossing_barrier: Cell::new(0),
+            check_win: Cell::new(xcb::NONE),
             // auto_float_types,
             // randr_base,
         })
@@ -403,10 +549,47 @@ impl XcbConnection {
         xcb::change_window_attributes_checked(&self.conn, self.root, ROOT_EVENT_MASK)
             .request_check()
             .context("Could not register SUBSTRUCTURE_NOTIFY/REDIRECT")?;
+        self.advertise_ewmh_support();
         // self.conn.flush();
         Ok(())
     }
 
+    /// Tell clients which EWMH hints we handle. Toolkits check _NET_SUPPORTED
+    /// before they send _NET_WM_STATE requests, e.g. to go fullscreen.
+    fn advertise_ewmh_support(&self) {
+        // EWMH requires a child window that points to itself and holds the
+        // name of the window manager
+        let check_win = self.conn.generate_id();
+        xcb::create_window(
+            &self.conn,                           // xcb connection to X11
+            xcb::COPY_FROM_PARENT as u8,          // new window's depth
+            check_win,                            // ID to be used for referring to the window
+            self.root,                            // parent window
+            0,                                    // x-coordinate
+            0,                                    // y-coordinate
+            1,                                    // width
+            1,                                    // height
+            0,                                    // border width
+            xcb::WINDOW_CLASS_INPUT_ONLY as u16,  // class
+            xcb::COPY_FROM_PARENT,                // visual
+            &[],                                  // value list
+        );
+        ewmh::set_supporting_wm_check(&self.conn, self.root, check_win);
+        ewmh::set_supporting_wm_check(&self.conn, check_win, check_win);
+        ewmh::set_wm_name(&self.conn, check_win, "wontwm");
+        self.check_win.set(check_win);
+
+        let mut supported = vec![
+            self.conn.SUPPORTED(),
+            self.conn.SUPPORTING_WM_CHECK(),
+            self.conn.WM_NAME(),
+            self.conn.WM_WINDOW_TYPE(),
+            self.conn.WM_STATE(),
+        ];
+        supported.extend(NetWmState::ALL.iter().map(|&state| self.net_wm_state_to_atom(state)));
+        ewmh::set_supported(&self.conn, self.preferred_screen, &supported);
+    }
+
     pub fn flush(&self) -> bool {
         self.conn.flush()
     }
@@ -425,25 +608,50 @@ impl XcbConnection {
         self.conn.flush();
     }
 
-    pub fn grab_button(&self) {
-        // TODO: this needs to be more configurable by the user
-        // for mouse_button in &[1, 3] {
-        //     // xcb docs: https://www.mankier.com/3/xcb_grab_button
-        //     xcb::grab_button(
-        //         &self.conn,             // xcb connection to X11
-        //         false,                  // don't pass grabbed events through to the window 
-        //         self.root,              // the window to grab: in this case the root window
-        //         MOUSE_MASK,             // which events are reported to the window
-        //         GRAB_MODE_ASYNC,        // don't lock pointer input while grabbing
-        //         GRAB_MODE_ASYNC,        // don't lock keyboard input while grabbing
-        //         xcb::NONE,              // don't confine the cursor to a specific window
-        //         xcb::NONE,              // don't change the cursor type
-        //         *mouse_button,          // the button to grab
-        //         xcb::MOD_MASK_4 as u16, // modifiers to grab
-        //     );
-        // }
-        // self.conn.flush();
+    /// Grab a mouse button on the root window. Pressing it with the modifiers
+    /// held grabs the pointer until the button is released, so that motion
+    /// events are reported to us in the meantime.
+    pub fn grab_button(&self, button: u8, modifiers: u16) {
+        // xcb docs: https://www.mankier.com/3/xcb_grab_button
+        xcb::grab_button(
+            &self.conn,      // xcb connection to X11
+            false,           // don't pass grabbed events through to the window
+            self.root,       // the window to grab: in this case the root window
+            MOUSE_MASK,      // which events are reported to the window
+            GRAB_MODE_ASYNC, // don't lock pointer input while grabbing
+            GRAB_MODE_ASYNC, // don't lock keyboard input while grabbing
+            xcb::NONE,       // don't confine the cursor to a specific window
+            xcb::NONE,       // don't change the cursor type
+            button,          // the button to grab
+            modifiers,       // modifiers to grab
+        );
+        self.conn.flush();
+    }
+
+    /// Release every mouse button grabbed with grab_button
+    pub fn ungrab_buttons(&self) {
+        xcb::ungrab_button(
+            &self.conn,
+            xcb::BUTTON_INDEX_ANY as u8,
+            self.root,
+            xcb::MOD_MASK_ANY as u16,
+        );
+    }
 
+    /**
+     * Drop the Enter events caused by the requests sent so far.
+     *
+     * Mapping, unmapping, moving or restacking windows makes the pointer cross
+     * into whichever window ends up under it, which is not the user moving the
+     * mouse. This remembers the sequence number of a new request and makes a
+     * round trip: Enter events reported before the server processed that
+     * request are then skipped.
+     */
+    pub fn ignore_crossing_events(&self) {
+        let sequence = xcb::no_operation(&self.conn).cookie.sequence as u16;
+        if xcb::get_input_focus(&self.conn).get_reply().is_ok() {
+            self.crossing_barrier.set(sequence);
+        }
     }
 
     pub fn register_events(&self, id: Window, events: u32) -> Result<()> {
@@ -482,7 +690,12 @@ impl XcbConnection {
             self.root, // the window to ungrab keys for
             xcb::MOD_MASK_ANY as u16,
         );
-        // xcb::destroy_window(&self.conn, self.check_win);
+        self.ungrab_buttons();
+        if self.check_win.get() != xcb::NONE {
+            xcb::delete_property(&self.conn, self.root, self.conn.SUPPORTED());
+            xcb::delete_property(&self.conn, self.root, self.conn.SUPPORTING_WM_CHECK());
+            xcb::destroy_window(&self.conn, self.check_win.get());
+        }
         // xcb::delete_property(&self.conn, self.root, self.conn.ACTIVE_WINDOW());
         self.focus_nothing();
         self.conn.flush();
@@ -498,6 +711,164 @@ impl XcbConnection {
         Ok(icccm::get_wm_class(&self.conn, win).get_reply()?.class().to_string())
     }
 
+    /// The _NET_WM_PID of a window, the process that owns it
+    pub fn get_wm_pid(&self, win: Window) -> Option<u32> {
+        ewmh::get_wm_pid(&self.conn, win).get_reply().ok()
+    }
+
+    /// The instance name, the first part of WM_CLASS
+    pub fn get_wm_instance(&self, win: Window) -> Result<String> {
+        Ok(icccm::get_wm_class(&self.conn, win).get_reply()?.instance().to_string())
+    }
+
+    /// The WM_WINDOW_ROLE of a window, set by clients with several kinds of
+    /// top level windows
+    pub fn get_window_role(&self, win: Window) -> Result<String> {
+        self.get_text_property(win, self.atoms.WM_WINDOW_ROLE)
+    }
+
+    /// The atom of the _NET_WM_STATE property and client message
+    pub fn net_wm_state_atom(&self) -> Atom {
+        self.conn.WM_STATE()
+    }
+
+    /// The atom of the ICCCM WM_CHANGE_STATE client message
+    pub fn wm_change_state_atom(&self) -> Atom {
+        self.atoms.WM_CHANGE_STATE
+    }
+
+    /// Set the ICCCM WM_STATE of a window to Iconic or Normal
+    pub fn set_iconic(&self, win: Window, iconic: bool) {
+        let state = if iconic { icccm::WM_STATE_ICONIC } else { icccm::WM_STATE_NORMAL };
+        icccm::set_wm_state(&self.conn, win, state, xcb::NONE);
+    }
+
+    fn net_wm_state_to_atom(&self, state: NetWmState) -> Atom {
+        match state {
+            NetWmState::Fullscreen => self.conn.WM_STATE_FULLSCREEN(),
+            NetWmState::DemandsAttention => self.conn.WM_STATE_DEMANDS_ATTENTION(),
+            NetWmState::Hidden => self.conn.WM_STATE_HIDDEN(),
+            NetWmState::Sticky => self.conn.WM_STATE_STICKY(),
+        }
+    }
+
+    /// The state an atom stands for, None for states we don't handle
+    pub fn net_wm_state_from_atom(&self, atom: Atom) -> Option<NetWmState> {
+        NetWmState::ALL
+            .iter()
+            .copied()
+            .find(|&state| self.net_wm_state_to_atom(state) == atom)
+    }
+
+    /// The _NET_WM_STATE of a window, restricted to the states we handle
+    pub fn get_net_wm_state(&self, win: Window) -> Vec<NetWmState> {
+        ewmh::get_wm_state(&self.conn, win)
+            .get_reply()
+            .map(|reply| {
+                reply
+                    .atoms()
+                    .iter()
+                    .filter_map(|&atom| self.net_wm_state_from_atom(atom))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Replace the _NET_WM_STATE of a window
+    pub fn set_net_wm_state(&self, win: Window, states: &[NetWmState]) {
+        let atoms: Vec<Atom> = states.iter().map(|&s| self.net_wm_state_to_atom(s)).collect();
+        ewmh::set_wm_state(&self.conn, win, &atoms);
+    }
+
+    /// The _NET_WM_WINDOW_TYPE of a window, without the _NET_WM_WINDOW_TYPE_
+    /// prefix (e.g. "DIALOG"), in order of preference.
+    pub fn get_window_types(&self, win: Window) -> Result<Vec<String>> {
+        let reply = ewmh::get_wm_window_type(&self.conn, win).get_reply()?;
+        let cookies: Vec<_> = reply
+            .atoms()
+            .iter()
+            .map(|&atom| xcb::get_atom_name(&self.conn, atom))
+            .collect();
+        let mut types = vec![];
+        for cookie in cookies {
+            let name = cookie.get_reply()?.name().to_string();
+            types.push(name.trim_start_matches("_NET_WM_WINDOW_TYPE_").to_string());
+        }
+        Ok(types)
+    }
+
+    /// The WM_TRANSIENT_FOR of a window: the window it is a dialog for
+    pub fn get_transient_for(&self, win: Window) -> Option<Window> {
+        xcb::get_property(&self.conn, false, win, xcb::ATOM_WM_TRANSIENT_FOR, xcb::ATOM_WINDOW, 0, 1)
+            .get_reply()
+            .ok()
+            .and_then(|reply| reply.value::<u32>().first().copied())
+            .filter(|&parent| parent != xcb::NONE)
+    }
+
+    /// The current geometry of a window, excluding its border
+    pub fn get_geometry(&self, win: Window) -> Result<Rectangle> {
+        let reply = xcb::get_geometry(&self.conn, win).get_reply()?;
+        Ok(Rectangle::new(
+            reply.x() as i32,
+            reply.y() as i32,
+            reply.width() as u32,
+            reply.height() as u32,
+        ))
+    }
+
+    /// Whether the urgency flag of the WM_HINTS of a window is set
+    pub fn get_urgency_hint(&self, win: Window) -> bool {
+        // Read by hand for the same reason as the size hints: WmHints::is_urgent
+        // compares the flags with 1
+        xcb::get_property(&self.conn, false, win, xcb::ATOM_WM_HINTS, xcb::ATOM_WM_HINTS, 0, 1)
+            .get_reply()
+            .ok()
+            .and_then(|reply| reply.value::<u32>().first().copied())
+            .is_some_and(|flags| flags & WM_HINT_X_URGENCY != 0)
+    }
+
+    /// Read the WM_NORMAL_HINTS of a window. Windows without the property
+    /// get empty hints.
+    pub fn get_size_hints(&self, win: Window) -> Result<SizeHints> {
+        // The property is decoded by hand: the icccm::SizeHints accessors test
+        // `flags & FLAG == 1`, so they never report anything but the first flag.
+        let reply = xcb::get_property(
+            &self.conn,
+            false,
+            win,
+            xcb::ATOM_WM_NORMAL_HINTS,
+            xcb::ATOM_WM_SIZE_HINTS,
+            0,
+            18,
+        ).get_reply()?;
+        let v: &[u32] = reply.value();
+        // Pre-ICCCM clients send 15 fields, without the base size and gravity
+        if v.len() < 15 {
+            return Ok(SizeHints::default());
+        }
+        let flags = v[0];
+        let pair = |flag: u32, i: usize| -> Option<(u32, u32)> {
+            if flags & flag != 0 && i + 1 < v.len() {
+                // The fields are signed on the wire
+                Some(((v[i] as i32).max(0) as u32, (v[i + 1] as i32).max(0) as u32))
+            } else {
+                None
+            }
+        };
+        let aspect = match (pair(SIZE_HINT_P_ASPECT, 11), pair(SIZE_HINT_P_ASPECT, 13)) {
+            (Some(min), Some(max)) => Some((min, max)),
+            _ => None,
+        };
+        Ok(SizeHints {
+            min: pair(SIZE_HINT_P_MIN_SIZE, 5),
+            max: pair(SIZE_HINT_P_MAX_SIZE, 7),
+            inc: pair(SIZE_HINT_P_RESIZE_INC, 9),
+            aspect,
+            base: pair(SIZE_HINT_BASE_SIZE, 15),
+        })
+    }
+
     pub fn get_text_property(&self, win: Window, atom: Atom) -> Result<String> {
         // Ok(icccm::get_text_property(&self.conn, win, atom).get_reply()?.name().to_string())
             // String::from_utf8(cookie.get_reply()?.value().to_vec())?
@@ -573,9 +944,26 @@ impl XcbConnection {
                         data: data,
                     })
                 }
-                // xcb::BUTTON_PRESS => None,
+                xcb::BUTTON_PRESS => {
+                    let e: &xcb::ButtonPressEvent = unsafe { xcb::cast_event(&event) };
+                    Some(XEvent::ButtonPress {
+                        id: e.child(),
+                        button: e.detail(),
+                        rpt: Point::new(e.root_x() as u32, e.root_y() as u32),
+                    })
+                }
+
+                xcb::BUTTON_RELEASE => {
+                    let e: &xcb::ButtonReleaseEvent = unsafe { xcb::cast_event(&event) };
+                    Some(XEvent::ButtonRelease { button: e.detail() })
+                }
 
-                // xcb::BUTTON_RELEASE => None,
+                xcb::MOTION_NOTIFY => {
+                    let e: &xcb::MotionNotifyEvent = unsafe { xcb::cast_event(&event) };
+                    Some(XEvent::MotionNotify {
+                        rpt: Point::new(e.root_x() as u32, e.root_y() as u32),
+                    })
+                }
 
                 xcb::KEY_PRESS => {
                     let e: &xcb::KeyPressEvent = unsafe { xcb::cast_event(&event) };
@@ -598,14 +986,25 @@ impl XcbConnection {
                         })
                 }
 
-                // xcb::ENTER_NOTIFY => {
-                //     let e: &xcb::EnterNotifyEvent = unsafe { xcb::cast_event(&event) };
-                //     Some(XEvent::Enter {
-                //         id: e.event(),
-                //         rpt: Point::new(e.root_x() as u32, e.root_y() as u32),
-                //         wpt: Point::new(e.event_x() as u32, e.event_y() as u32),
-                //     })
-                // }
+                xcb::ENTER_NOTIFY => {
+                    let e: &xcb::EnterNotifyEvent = unsafe { xcb::cast_event(&event) };
+                    let sequence = unsafe { (*event.ptr).sequence };
+                    // Sequence numbers wrap around, so compare their distance
+                    let caused_by_us = (self.crossing_barrier.get().wrapping_sub(sequence) as i16) > 0;
+                    // Grabs (e.g. mouse drags) and entering from a child window
+                    // don't move the pointer to a new window either
+                    if caused_by_us
+                        || e.mode() != xcb::NOTIFY_MODE_NORMAL as u8
+                        || e.detail() == xcb::NOTIFY_DETAIL_INFERIOR as u8
+                    {
+                        return None;
+                    }
+                    Some(XEvent::Enter {
+                        id: e.event(),
+                        rpt: Point::new(e.root_x() as u32, e.root_y() as u32),
+                        wpt: Point::new(e.event_x() as u32, e.event_y() as u32),
+                    })
+                }
 
                 // xcb::LEAVE_NOTIFY => {
                 //     let e: &xcb::LeaveNotifyEvent = unsafe { xcb::cast_event(&event) };
@@ -854,3 +1253,61 @@ impl XcbConnection {
 
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constrain_without_hints_keeps_the_size() {
+        let hints = SizeHints::default();
+        assert_eq!(hints.constrain(100, 50), (100, 50));
+        assert_eq!(hints.constrain(0, 0), (1, 1));
+    }
+
+    #[test]
+    fn constrain_bounds_the_size_by_min_and_max() {
+        let hints = SizeHints { min: Some((50, 40)), max: Some((200, 100)), ..SizeHints::default() };
+        assert_eq!(hints.constrain(30, 30), (50, 40));
+        assert_eq!(hints.constrain(300, 300), (200, 100));
+        assert_eq!(hints.constrain(120, 60), (120, 60));
+
+        // The min size wins over a smaller max size
+        let hints = SizeHints { min: Some((50, 50)), max: Some((20, 20)), ..SizeHints::default() };
+        assert_eq!(hints.constrain(100, 100), (50, 50));
+    }
+
+    #[test]
+    fn constrain_counts_increments_from_the_base_size() {
+        let hints = SizeHints { base: Some((4, 2)), inc: Some((10, 20)), ..SizeHints::default() };
+        assert_eq!(hints.constrain(100, 50), (94, 42));
+        assert_eq!(hints.constrain(104, 62), (104, 62));
+
+        // Without a base size the min size is used
+        let hints = SizeHints { min: Some((5, 5)), inc: Some((10, 10)), ..SizeHints::default() };
+        assert_eq!(hints.constrain(100, 100), (95, 95));
+        assert_eq!(hints.constrain(3, 3), (5, 5));
+
+        let hints = SizeHints { inc: Some((10, 0)), ..SizeHints::default() };
+        assert_eq!(hints.constrain(95, 95), (90, 95));
+    }
+
+    #[test]
+    fn constrain_clamps_the_aspect_ratio() {
+        let hints = SizeHints { aspect: Some(((1, 1), (2, 1))), ..SizeHints::default() };
+        assert_eq!(hints.constrain(300, 100), (200, 100));
+        assert_eq!(hints.constrain(50, 100), (50, 50));
+        assert_eq!(hints.constrain(150, 100), (150, 100));
+
+        // The ratio applies to the size minus the base size
+        let hints = SizeHints { base: Some((10, 0)), aspect: Some(((1, 1), (1, 1))), ..SizeHints::default() };
+        assert_eq!(hints.constrain(110, 50), (60, 50));
+    }
+
+    #[test]
+    fn fit_centers_the_window_in_its_slot() {
+        let hints = SizeHints { max: Some((50, 40)), ..SizeHints::default() };
+        assert_eq!(hints.fit(&Rectangle::new(10, 10, 100, 100)).values(), (35, 40, 50, 40));
+        assert_eq!(SizeHints::default().fit(&Rectangle::new(10, 10, 100, 100)).values(), (10, 10, 100, 100));
+    }
+}